@@ -0,0 +1,67 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+
+use ja::Lexer;
+
+const SIZES: [usize; 3] = [100 * 1024, 1024 * 1024, 10 * 1024 * 1024];
+
+// Repeats a small but varied Ja program until it is at least `size` bytes long.
+fn generate_source(size: usize) -> String {
+    let snippet = "fun area(width, height) {
+    let größe = width * height + 0x2A;
+    // Halve large areas.
+    if größe >= 1_000 { return größe / 2.5e0; }
+    while height != 0 { height -= 1; }
+    return \"done: {größe}\";
+}
+";
+
+    let mut source = String::with_capacity(size + snippet.len());
+    while source.len() < size {
+        source.push_str(snippet);
+    }
+    source
+}
+
+fn count_tokens(source: &str) -> usize {
+    Lexer::new("bench.ja", source).count()
+}
+
+fn count_borrowed_tokens(source: &str) -> usize {
+    Lexer::new("bench.ja", source).borrowed_tokens().count()
+}
+
+fn bench_tokenize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize");
+    group.sample_size(10);
+
+    for size in SIZES {
+        let source = generate_source(size);
+
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &source, |b, source| {
+            b.iter(|| count_tokens(black_box(source)))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_tokenize_borrowed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize_borrowed");
+    group.sample_size(10);
+
+    for size in SIZES {
+        let source = generate_source(size);
+
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &source, |b, source| {
+            b.iter(|| count_borrowed_tokens(black_box(source)))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_tokenize, bench_tokenize_borrowed);
+criterion_main!(benches);
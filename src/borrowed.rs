@@ -0,0 +1,107 @@
+//! Zero-copy counterparts of `Token` and `TokenValue`, produced by `Lexer::next_borrowed`.
+//! Identifiers, strings and doc comments borrow from the source unless escapes or normalization
+//! forced a decoded copy, and tokens whose text follows from their kind (punctuation, keywords,
+//! `Eof`) carry no payload at all.
+
+use alloc::borrow::Cow;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use crate::lexer::{self, NumberSuffix, TokenType, Trivia};
+use crate::source::Span;
+use crate::symbol::Symbol;
+
+/// The payload of a borrowed token. `None` for tokens whose text is their only value.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum TokenValue<'src> {
+    None,
+    Bool(bool),
+    Char(char),
+    /// May be one past the largest value of its type, as for the owned `TokenValue::Int`.
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(Cow<'src, str>),
+    Null
+}
+
+/// A token borrowing its text from the source, produced by `Lexer::next_borrowed`.
+#[derive(Clone, Debug)]
+pub struct Token<'src> {
+    pub(crate) kind: TokenType,
+    pub(crate) value: TokenValue<'src>,
+    pub(crate) text: &'src str,
+    pub(crate) span: Span,
+    pub(crate) suffix: Option<NumberSuffix>,
+    pub(crate) symbol: Option<Symbol>,
+    pub(crate) trivia: Vec<Trivia>
+}
+
+impl<'src> Token<'src> {
+    pub(crate) fn new(kind: TokenType, value: TokenValue<'src>, text: &'src str, span: Span) -> Self {
+        Self {
+            kind, value, text, span, suffix: None, symbol: None, trivia: Vec::new()
+        }
+    }
+
+    pub fn kind(&self) -> TokenType {
+        self.kind
+    }
+
+    /// `TokenValue::None` for tokens whose text is their only value.
+    pub fn value(&self) -> &TokenValue<'src> {
+        &self.value
+    }
+
+    /// The exact source text of the token, before any escape decoding.
+    pub fn text(&self) -> &'src str {
+        self.text
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn contextual_keyword(&self) -> Option<TokenType> {
+        match self.kind {
+            TokenType::Identifier => lexer::contextual_keyword(self.text),
+            _ => None
+        }
+    }
+
+    pub fn suffix(&self) -> Option<NumberSuffix> {
+        self.suffix
+    }
+
+    pub fn symbol(&self) -> Option<Symbol> {
+        self.symbol
+    }
+
+    pub fn trivia(&self) -> &[Trivia] {
+        &self.trivia
+    }
+
+    /// Payload-free tokens get their source text as the owned value, matching what `Lexer::next_token` returns.
+    pub fn into_owned(self) -> lexer::Token {
+        let value = match self.value {
+            TokenValue::None => lexer::TokenValue::String(self.text.to_string()),
+            TokenValue::Bool(value) => lexer::TokenValue::Bool(value),
+            TokenValue::Char(value) => lexer::TokenValue::Char(value),
+            TokenValue::Int(value) => lexer::TokenValue::Int(value),
+            TokenValue::UInt(value) => lexer::TokenValue::UInt(value),
+            TokenValue::Float(value) => lexer::TokenValue::Float(value),
+            TokenValue::Str(value) => lexer::TokenValue::String(value.into_owned()),
+            TokenValue::Null => lexer::TokenValue::Null
+        };
+
+        lexer::Token {
+            kind: self.kind,
+            value,
+            span: self.span,
+            suffix: self.suffix,
+            symbol: self.symbol,
+            trivia: self.trivia
+        }
+    }
+}
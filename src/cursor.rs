@@ -0,0 +1,79 @@
+use alloc::vec::Vec;
+
+use crate::error::LexError;
+use crate::lexer::{Lexer, Token, TokenType};
+
+/// A position in a `TokenCursor` to rewind to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Checkpoint(usize);
+
+/// Buffers the tokens of a `Lexer` so a parser can look arbitrarily far ahead and backtrack to
+/// a checkpoint without lexing anything twice.
+pub struct TokenCursor<'src> {
+    lexer: Lexer<'src>,
+    tokens: Vec<Token>,
+    position: usize
+}
+
+impl<'src> TokenCursor<'src> {
+    pub fn new(lexer: Lexer<'src>) -> Self {
+        Self {
+            lexer, tokens: Vec::new(), position: 0
+        }
+    }
+
+    // Lexes until the token `offset` places ahead of the cursor is buffered, or `Eof` is. Uses
+    // `next_token` rather than the iterator, so a lexer that was already run to the end still
+    // yields its `Eof` and the buffer is never empty.
+    fn fill(&mut self, offset: usize) {
+        while self.tokens.len() <= self.position + offset {
+            if self.tokens.last().is_some_and(|token| matches!(token.kind, TokenType::Eof)) {
+                break;
+            }
+
+            self.tokens.push(self.lexer.next_token());
+        }
+    }
+
+    /// The next token, without consuming it.
+    pub fn peek(&mut self) -> &Token {
+        self.peek_nth(0)
+    }
+
+    /// Looking past the end of the input keeps returning the `Eof` token.
+    pub fn peek_nth(&mut self, offset: usize) -> &Token {
+        self.fill(offset);
+
+        let index = (self.position + offset).min(self.tokens.len() - 1);
+        &self.tokens[index]
+    }
+
+    pub fn is_at_end(&mut self) -> bool {
+        matches!(self.peek().kind, TokenType::Eof)
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.position)
+    }
+
+    /// Moves back to `checkpoint`; the tokens after it are served again from the buffer.
+    pub fn rewind(&mut self, checkpoint: Checkpoint) {
+        self.position = checkpoint.0;
+    }
+
+    pub fn errors(&self) -> &[LexError] {
+        self.lexer.errors()
+    }
+}
+
+impl Iterator for TokenCursor<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.fill(0);
+
+        let token = self.tokens.get(self.position)?.clone();
+        self.position += 1;
+        Some(token)
+    }
+}
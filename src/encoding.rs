@@ -0,0 +1,219 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+/// A text encoding `decode` can read.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1
+}
+
+impl Encoding {
+    /// Accepts the usual spellings of an encoding name, e.g. from an editor modeline.
+    pub fn from_label(label: &str) -> Option<Self> {
+        let encoding = match label.trim().to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Encoding::Utf8,
+            "utf-16le" | "utf16le" => Encoding::Utf16Le,
+            "utf-16be" | "utf16be" => Encoding::Utf16Be,
+            "latin-1" | "latin1" | "iso-8859-1" | "iso8859-1" => Encoding::Latin1,
+            _ => return None
+        };
+
+        Some(encoding)
+    }
+
+    /// The encoding announced by a byte order mark at the start of `bytes`, if any.
+    pub fn from_bom(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0xEF, 0xBB, 0xBF, ..] => Some(Encoding::Utf8),
+            [0xFF, 0xFE, ..] => Some(Encoding::Utf16Le),
+            [0xFE, 0xFF, ..] => Some(Encoding::Utf16Be),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "Latin-1"
+        };
+
+        f.write_str(name)
+    }
+}
+
+// A stretch of characters that each take `decoded_width` bytes of UTF-8 and `original_width`
+// bytes of the original encoding. Widths are reduced, so any 1:1 stretch is a single run.
+#[derive(Copy, Clone, Debug)]
+struct Run {
+    decoded: usize,
+    original: usize,
+    decoded_width: u8,
+    original_width: u8
+}
+
+/// Maps byte offsets in the decoded UTF-8 text back to byte offsets in the original input.
+#[derive(Clone, Debug, Default)]
+pub struct OffsetMap {
+    runs: Vec<Run>
+}
+
+impl OffsetMap {
+    fn push(&mut self, decoded: usize, original: usize, decoded_width: usize, original_width: usize) {
+        let (decoded_width, original_width) = if decoded_width == original_width { (1, 1) } else { (decoded_width as u8, original_width as u8) };
+
+        if let Some(last) = self.runs.last() {
+            if (last.decoded_width, last.original_width) == (decoded_width, original_width) {
+                return;
+            }
+        }
+
+        self.runs.push(Run { decoded, original, decoded_width, original_width });
+    }
+
+    /// `offset` should be a character boundary of the decoded text.
+    pub fn original_offset(&self, offset: usize) -> usize {
+        let index = self.runs.partition_point(|run| run.decoded <= offset);
+
+        match index.checked_sub(1).map(|index| self.runs[index]) {
+            Some(run) => run.original + (offset - run.decoded) / run.decoded_width as usize * run.original_width as usize,
+            None => offset
+        }
+    }
+}
+
+/// The result of `decode`.
+pub struct DecodedSource {
+    pub(crate) text: String,
+    pub(crate) encoding: Encoding,
+    pub(crate) offsets: OffsetMap,
+    pub(crate) invalid: Vec<Range<usize>>
+}
+
+impl DecodedSource {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn offsets(&self) -> &OffsetMap {
+        &self.offsets
+    }
+
+    /// Where in the decoded text each invalid sequence was replaced by U+FFFD.
+    pub fn invalid(&self) -> &[Range<usize>] {
+        &self.invalid
+    }
+}
+
+/// Decodes `bytes` to UTF-8 using the encoding of its byte order mark, else `declared`, else UTF-8.
+/// A byte order mark is decoded to U+FEFF, which the lexer skips, so it still lines up with the
+/// original bytes. Invalid sequences become U+FFFD and are recorded rather than rejected.
+pub fn decode(bytes: &[u8], declared: Option<Encoding>) -> DecodedSource {
+    let encoding = Encoding::from_bom(bytes).or(declared).unwrap_or(Encoding::Utf8);
+
+    let mut decoder = Decoder { text: String::with_capacity(bytes.len()), offsets: OffsetMap::default(), invalid: Vec::new(), original: 0 };
+
+    match encoding {
+        Encoding::Utf8 => decoder.utf8(bytes),
+        Encoding::Utf16Le => decoder.utf16(bytes, u16::from_le_bytes),
+        Encoding::Utf16Be => decoder.utf16(bytes, u16::from_be_bytes),
+        Encoding::Latin1 => decoder.latin1(bytes)
+    }
+
+    DecodedSource {
+        text: decoder.text, encoding, offsets: decoder.offsets, invalid: decoder.invalid
+    }
+}
+
+struct Decoder {
+    text: String,
+    offsets: OffsetMap,
+    invalid: Vec<Range<usize>>,
+    // Bytes of the input decoded so far.
+    original: usize
+}
+
+impl Decoder {
+    fn push(&mut self, c: char, original_width: usize) {
+        self.offsets.push(self.text.len(), self.original, c.len_utf8(), original_width);
+        self.text.push(c);
+        self.original += original_width;
+    }
+
+    fn push_invalid(&mut self, original_width: usize) {
+        let start = self.text.len();
+        self.push(char::REPLACEMENT_CHARACTER, original_width);
+        self.invalid.push(start..self.text.len());
+    }
+
+    fn utf8(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let (valid, invalid) = match core::str::from_utf8(bytes) {
+                Ok(text) => (text, None),
+                Err(error) => {
+                    // Only the prefix checked above is converted.
+                    let text = core::str::from_utf8(&bytes[..error.valid_up_to()]).unwrap();
+                    (text, Some(error.error_len().unwrap_or(bytes.len() - error.valid_up_to())))
+                }
+            };
+
+            // Valid UTF-8 maps onto itself, so it is one run however many characters it holds.
+            if !valid.is_empty() {
+                self.offsets.push(self.text.len(), self.original, 1, 1);
+                self.text.push_str(valid);
+                self.original += valid.len();
+            }
+
+            bytes = &bytes[valid.len()..];
+
+            if let Some(length) = invalid {
+                self.push_invalid(length);
+                bytes = &bytes[length..];
+            }
+        }
+    }
+
+    fn utf16(&mut self, bytes: &[u8], unit: fn([u8; 2]) -> u16) {
+        let mut units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]])).peekable();
+
+        while let Some(first) = units.next() {
+            match first {
+                0xD800..=0xDBFF => match units.peek() {
+                    Some(&second @ 0xDC00..=0xDFFF) => {
+                        units.next();
+                        let scalar = 0x10000 + (((first as u32) - 0xD800) << 10) + ((second as u32) - 0xDC00);
+                        self.push(char::from_u32(scalar).unwrap(), 4);
+                    }
+                    _ => self.push_invalid(2)
+                },
+                0xDC00..=0xDFFF => self.push_invalid(2),
+                _ => self.push(char::from_u32(first as u32).unwrap(), 2)
+            }
+        }
+
+        // A stray last byte cannot be a whole code unit.
+        if bytes.len() % 2 == 1 {
+            self.push_invalid(1);
+        }
+    }
+
+    fn latin1(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.push(byte as char, 1);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use alloc::string::String;
+use core::fmt;
+
+use crate::encoding::Encoding;
+use crate::source::Span;
+
+/// An error or warning found while lexing, with the span it applies to.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum LexError {
+    UnexpectedCharacter { character: char, span: Span },
+    UnterminatedString { span: Span },
+    UnterminatedChar { span: Span },
+    UnterminatedComment { span: Span },
+    EmptyChar { span: Span },
+    MultipleChars { span: Span },
+    InvalidEscape { sequence: String, span: Span },
+    MalformedNumber { span: Span },
+    Overflow { span: Span },
+    MixedScriptIdentifier { span: Span },
+    BidiControl { character: char, span: Span },
+    InvisibleCharacter { character: char, span: Span },
+    ConfusableIdentifier { name: String, other: String, span: Span },
+    InvalidEncoding { encoding: Encoding, span: Span }
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnexpectedCharacter { span, .. }
+            | LexError::UnterminatedString { span }
+            | LexError::UnterminatedChar { span }
+            | LexError::UnterminatedComment { span }
+            | LexError::EmptyChar { span }
+            | LexError::MultipleChars { span }
+            | LexError::InvalidEscape { span, .. }
+            | LexError::MalformedNumber { span }
+            | LexError::Overflow { span }
+            | LexError::MixedScriptIdentifier { span }
+            | LexError::BidiControl { span, .. }
+            | LexError::InvisibleCharacter { span, .. }
+            | LexError::ConfusableIdentifier { span, .. }
+            | LexError::InvalidEncoding { span, .. } => *span
+        }
+    }
+
+    pub(crate) fn span_mut(&mut self) -> &mut Span {
+        match self {
+            LexError::UnexpectedCharacter { span, .. }
+            | LexError::UnterminatedString { span }
+            | LexError::UnterminatedChar { span }
+            | LexError::UnterminatedComment { span }
+            | LexError::EmptyChar { span }
+            | LexError::MultipleChars { span }
+            | LexError::InvalidEscape { span, .. }
+            | LexError::MalformedNumber { span }
+            | LexError::Overflow { span }
+            | LexError::MixedScriptIdentifier { span }
+            | LexError::BidiControl { span, .. }
+            | LexError::InvisibleCharacter { span, .. }
+            | LexError::ConfusableIdentifier { span, .. }
+            | LexError::InvalidEncoding { span, .. } => span
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::UnexpectedCharacter { character, .. } => write!(f, "unexpected character `{}`", character.escape_debug()),
+            LexError::UnterminatedString { .. } => write!(f, "unterminated string literal"),
+            LexError::UnterminatedChar { .. } => write!(f, "unterminated character literal"),
+            LexError::UnterminatedComment { .. } => write!(f, "unterminated block comment"),
+            LexError::EmptyChar { .. } => write!(f, "empty character literal"),
+            LexError::MultipleChars { .. } => write!(f, "character literal may only contain one character"),
+            LexError::InvalidEscape { sequence, .. } => write!(f, "invalid escape sequence `{}`", sequence),
+            LexError::MalformedNumber { .. } => write!(f, "malformed number literal"),
+            LexError::Overflow { .. } => write!(f, "number literal is out of range"),
+            LexError::MixedScriptIdentifier { .. } => write!(f, "identifier mixes characters from different scripts"),
+            LexError::BidiControl { character, .. } => write!(f, "bidirectional control character `{}` can make code display differently from how it runs", character.escape_unicode()),
+            LexError::InvisibleCharacter { character, .. } => write!(f, "invisible character `{}` in identifier", character.escape_unicode()),
+            LexError::ConfusableIdentifier { name, other, .. } => write!(f, "identifier `{}` looks like `{}`", name, other),
+            LexError::InvalidEncoding { encoding, .. } => write!(f, "invalid {} sequence", encoding)
+        }
+    }
+}
+
+impl core::error::Error for LexError {}
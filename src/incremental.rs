@@ -0,0 +1,226 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::error::LexError;
+use crate::lexer::{is_line_break, Lexer, LexerOptions, Token, TokenType};
+use crate::source::{FileId, Position, Span};
+use crate::symbol::{Identifiers, SymbolTable};
+
+/// Keeps the tokens of a file up to date as it is edited, re-lexing only around each edit.
+///
+/// An edit restarts the lexer at the end of the last token that is outside every string and
+/// separated from the edit by a line break, since no token looks ahead across one. Re-lexing stops
+/// as soon as the lexer is again outside every string at a position where an old token started,
+/// on a line after the edit, having seen the same identifiers first for confusable checks: from
+/// there on the old tokens and their warnings are still valid once shifted.
+pub struct IncrementalLexer {
+    file: FileId,
+    path: String,
+    text: String,
+    options: LexerOptions,
+    symbols: SymbolTable,
+    tokens: Vec<Token>,
+    errors: Vec<LexError>,
+    warnings: Vec<LexError>
+}
+
+impl IncrementalLexer {
+    /// Lexes `text` in full; later edits only re-lex what they touch.
+    pub fn new(path: impl Into<String>, text: impl Into<String>) -> Self {
+        let mut incremental = Self {
+            file: FileId(0), path: path.into(), text: text.into(), options: LexerOptions::default(), symbols: SymbolTable::new(), tokens: Vec::new(), errors: Vec::new(), warnings: Vec::new()
+        };
+
+        incremental.relex_all();
+        incremental
+    }
+
+    /// Re-lexes the whole text with the new options.
+    pub fn with_options(mut self, options: LexerOptions) -> Self {
+        self.options = options;
+        self.relex_all();
+        self
+    }
+
+    /// Re-lexes the whole text, interning names into `symbols`.
+    pub fn with_symbols(mut self, symbols: SymbolTable) -> Self {
+        self.symbols = symbols;
+        self.relex_all();
+        self
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    pub fn warnings(&self) -> &[LexError] {
+        &self.warnings
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    fn lexer(&self) -> Lexer<'_> {
+        Lexer::with_file(self.file, &self.path, &self.text).with_options(self.options).with_symbols(self.symbols.clone())
+    }
+
+    fn relex_all(&mut self) {
+        let mut lexer = self.lexer();
+        let tokens = lexer.by_ref().collect();
+        let errors = lexer.take_errors();
+        let warnings = lexer.take_warnings();
+
+        self.tokens = tokens;
+        self.errors = errors;
+        self.warnings = warnings;
+    }
+
+    /// Replaces the bytes in `range` with `replacement` and returns the indices of the tokens that
+    /// were re-lexed. Panics if `range` does not lie on character boundaries.
+    pub fn edit(&mut self, range: Range<usize>, replacement: &str) -> Range<usize> {
+        // Number of open interpolated strings before each token, and after the last one.
+        let mut depths = Vec::with_capacity(self.tokens.len() + 1);
+        let mut depth = 0u32;
+        for token in &self.tokens {
+            depths.push(depth);
+            match token.kind {
+                TokenType::StringStart => depth += 1,
+                TokenType::StringEnd => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        depths.push(depth);
+
+        let restart = (1..self.tokens.len()).rev().find(|&index| {
+            let boundary = token_boundary(&self.tokens, index).offset;
+            depths[index] == 0 && boundary <= range.start && self.text[boundary..range.start].contains(is_line_break)
+        }).unwrap_or(0);
+
+        let start = token_boundary(&self.tokens, restart);
+
+        self.text.replace_range(range.clone(), replacement);
+
+        let offset_delta = replacement.len() as i64 - range.len() as i64;
+        let mut line_delta = 0;
+
+        // Counting a `\r` just before the end as a line break even if a `\n` follows it only makes
+        // resynchronizing a little more cautious.
+        let edit_end = range.start + replacement.len();
+        let edit_end_line = start.line + self.text[start.offset..edit_end].matches(is_line_break).count() as u32;
+
+        let mut identifiers = Identifiers::default();
+        note_identifiers(&mut identifiers, &self.symbols, &self.tokens[..restart]);
+
+        let mut lexer = self.lexer().starting_at(start).with_identifiers(identifiers.clone());
+        // The identifiers of the old text up to token `old`.
+        let mut old_identifiers = identifiers;
+        let mut relexed = Vec::new();
+        let mut resync = None;
+        let mut old = restart;
+
+        loop {
+            let position = lexer.current_position();
+
+            if lexer.is_at_top_level() && position.offset >= edit_end && position.line > edit_end_line {
+                let old_offset = (position.offset as i64 - offset_delta) as usize;
+
+                while old < self.tokens.len() && token_boundary(&self.tokens, old).offset < old_offset {
+                    note_identifiers(&mut old_identifiers, &self.symbols, &self.tokens[old..=old]);
+                    old += 1;
+                }
+
+                let boundary = token_boundary(&self.tokens, old);
+                if old < self.tokens.len() && boundary.offset == old_offset && depths[old] == 0 && lexer.identifiers().same_as(&old_identifiers) {
+                    line_delta = position.line as i64 - boundary.line as i64;
+                    resync = Some(old);
+                    break;
+                }
+            }
+
+            let token = lexer.next_token();
+            let eof = matches!(token.kind, TokenType::Eof);
+            relexed.push(token);
+
+            if eof {
+                break;
+            }
+        }
+
+        let resync_offset = resync.map_or(usize::MAX, |old| token_boundary(&self.tokens, old).offset);
+
+        let new_errors = lexer.take_errors();
+        let new_warnings = lexer.take_warnings();
+
+        let relexed_count = relexed.len();
+        let mut tail = match resync {
+            Some(old) => self.tokens.split_off(old),
+            None => Vec::new()
+        };
+
+        for token in &mut tail {
+            token.span = shift(token.span, offset_delta, line_delta);
+            for trivia in &mut token.trivia {
+                trivia.span = shift(trivia.span, offset_delta, line_delta);
+            }
+        }
+
+        self.tokens.truncate(restart);
+        self.tokens.extend(relexed);
+        self.tokens.append(&mut tail);
+
+        splice_diagnostics(&mut self.errors, new_errors, start.offset, resync_offset, offset_delta, line_delta);
+        splice_diagnostics(&mut self.warnings, new_warnings, start.offset, resync_offset, offset_delta, line_delta);
+
+        restart..restart + relexed_count
+    }
+}
+
+// Where the lexer was when it started on token `index`, i.e. where the token's leading trivia begins.
+fn token_boundary(tokens: &[Token], index: usize) -> Position {
+    index.checked_sub(1).map_or(Position::new(0, 1, 1), |previous| tokens[previous].span.end)
+}
+
+// Records the identifiers among `tokens` as the lexer did when it produced them.
+fn note_identifiers(identifiers: &mut Identifiers, symbols: &SymbolTable, tokens: &[Token]) {
+    for token in tokens.iter().filter(|token| matches!(token.kind, TokenType::Identifier)) {
+        if let Some(symbol) = token.symbol {
+            identifiers.insert(symbols.skeleton_key(symbol), symbol, token.span.start.offset);
+        }
+    }
+}
+
+// Diagnostics before `start` are kept, those from re-lexed text replaced, and those after the
+// resynchronization point `end` (in old offsets) shifted along with their tokens.
+fn splice_diagnostics(diagnostics: &mut Vec<LexError>, relexed: Vec<LexError>, start: usize, end: usize, offset_delta: i64, line_delta: i64) {
+    let mut tail = diagnostics.iter().filter(|diagnostic| diagnostic.span().start.offset >= end).cloned().collect::<Vec<_>>();
+    for diagnostic in &mut tail {
+        let span = diagnostic.span_mut();
+        *span = shift(*span, offset_delta, line_delta);
+    }
+
+    diagnostics.retain(|diagnostic| diagnostic.span().start.offset < start);
+    diagnostics.extend(relexed);
+    diagnostics.extend(tail);
+}
+
+// Moves a span that lies on a line after an edit. Columns are unchanged since the text before it
+// on its line is.
+fn shift(span: Span, offset_delta: i64, line_delta: i64) -> Span {
+    let shift_position = |position: Position| Position::new((position.offset as i64 + offset_delta) as usize, (position.line as i64 + line_delta) as u32, position.column);
+
+    Span::new(span.file, shift_position(span.start), shift_position(span.end))
+}
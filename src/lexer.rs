@@ -1,15 +1,35 @@
-use lazy_static::lazy_static;
-use std::collections::HashMap;
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::fmt;
+use core::num::IntErrorKind;
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+use unicode_security::MixedScript;
 
-#[derive(Copy, Clone, Debug)]
+use crate::borrowed;
+use crate::error::LexError;
+use crate::source::{FileId, Position, Span};
+use crate::symbol::{Identifiers, Symbol, SymbolTable};
+
+/// The kind of a token.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum TokenType {
     Eof,
 
     BoolVal,
+    NullVal,
     CharVal,
     IntVal,
     FloatVal,
     StringVal,
+    StringStart,
+    StringPart,
+    StringEnd,
+    DocComment,
+    InnerDocComment,
     Identifier,
 
     Plus,
@@ -17,8 +37,19 @@ pub enum TokenType {
     Multiply,
     Divide,
     Modulus,
+    Power,
 
     Assign,
+    PlusAssign,
+    MinusAssign,
+    MultiplyAssign,
+    DivideAssign,
+    ModulusAssign,
+    BitwiseAndAssign,
+    BitwiseOrAssign,
+    BitwiseXorAssign,
+    BitwiseShiftLeftAssign,
+    BitwiseShiftRightAssign,
 
     LessThan,
     GreaterThan,
@@ -38,6 +69,10 @@ pub enum TokenType {
     BitwiseShiftLeft,
     BitwiseShiftRight,
 
+    Range,
+    RangeInclusive,
+    Question,
+
     LeftParen,
     RightParen,
     LeftBracket,
@@ -47,7 +82,11 @@ pub enum TokenType {
     Dot,
     Comma,
     Colon,
+    DoubleColon,
     SemiColon,
+    Arrow,
+    FatArrow,
+    Ellipsis,
 
     Let,
     Const,
@@ -55,6 +94,16 @@ pub enum TokenType {
     Struct,
     Fun,
     Namespace,
+    Import,
+    Pub,
+    Impl,
+    Trait,
+    SelfValue,
+    Mut,
+
+    As,
+    In,
+    Type,
 
     If,
     Elif,
@@ -68,261 +117,1315 @@ pub enum TokenType {
     Break,
     Continue,
     Return,
+    Match,
+    Loop,
 
     Error
 }
 
+impl TokenType {
+    /// Keyword token types, including the contextual keywords `as`, `in` and `type`.
+    pub fn is_keyword(self) -> bool {
+        matches!(self,
+            TokenType::Let | TokenType::Const | TokenType::Enum | TokenType::Struct | TokenType::Fun | TokenType::Namespace | TokenType::Import | TokenType::Pub | TokenType::Impl | TokenType::Trait | TokenType::SelfValue | TokenType::Mut
+            | TokenType::As | TokenType::In | TokenType::Type
+            | TokenType::If | TokenType::Elif | TokenType::Else | TokenType::Switch | TokenType::Case | TokenType::Default | TokenType::For | TokenType::While | TokenType::Do | TokenType::Break | TokenType::Continue | TokenType::Return | TokenType::Match | TokenType::Loop)
+    }
+
+    /// Pieces of interpolated strings count as literals too.
+    pub fn is_literal(self) -> bool {
+        matches!(self,
+            TokenType::BoolVal | TokenType::NullVal | TokenType::CharVal | TokenType::IntVal | TokenType::FloatVal | TokenType::StringVal
+            | TokenType::StringStart | TokenType::StringPart | TokenType::StringEnd)
+    }
+
+    pub fn is_operator(self) -> bool {
+        operator(self).is_some()
+    }
+
+    /// `=` and the compound assignments such as `+=`.
+    pub fn is_assignment(self) -> bool {
+        operator(self).is_some_and(|operator| operator.assignment)
+    }
+
+    /// How tightly the operator binds as a binary operator, higher binding tighter, or `None` if it
+    /// is not one.
+    pub fn binary_precedence(self) -> Option<u8> {
+        operator(self).and_then(|operator| operator.precedence)
+    }
+
+    /// How a chain of this binary operator groups, or `None` if it is not one.
+    pub fn associativity(self) -> Option<Associativity> {
+        operator(self).and_then(|operator| operator.associativity)
+    }
+
+    /// Operators that can stand before their operand: `-`, `!` and `~`.
+    pub fn is_unary_prefix(self) -> bool {
+        operator(self).is_some_and(|operator| operator.prefix)
+    }
+
+    /// The source text of punctuation and keywords, e.g. `"+="` for `PlusAssign`.
+    pub fn text(self) -> Option<&'static str> {
+        token_text(self)
+    }
+}
+
+/// Fixed tokens display as their text and the rest as a description, e.g. for "expected `;`, found
+/// identifier".
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(text) = self.text() {
+            return write!(f, "`{}`", text);
+        }
+
+        let description = match self {
+            TokenType::Eof => "end of file",
+            TokenType::BoolVal => "boolean literal",
+            TokenType::NullVal => "null literal",
+            TokenType::CharVal => "character literal",
+            TokenType::IntVal => "integer literal",
+            TokenType::FloatVal => "float literal",
+            TokenType::StringVal => "string literal",
+            TokenType::StringStart => "start of interpolated string",
+            TokenType::StringPart => "part of interpolated string",
+            TokenType::StringEnd => "end of interpolated string",
+            TokenType::DocComment => "doc comment",
+            TokenType::InnerDocComment => "inner doc comment",
+            TokenType::Identifier => "identifier",
+            _ => "invalid token"
+        };
+
+        f.write_str(description)
+    }
+}
+
+/// How a chain of one binary operator groups, e.g. `a - b - c`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Associativity {
+    Left,
+    Right,
+    /// Chaining is an error, e.g. `a < b < c`.
+    NonAssociative
+}
+
+struct Operator {
+    text: &'static str,
+    kind: TokenType,
+    precedence: Option<u8>,
+    associativity: Option<Associativity>,
+    prefix: bool,
+    assignment: bool
+}
+
+impl Operator {
+    const fn binary(text: &'static str, kind: TokenType, precedence: u8, associativity: Associativity) -> Self {
+        Self {
+            text, kind, precedence: Some(precedence), associativity: Some(associativity), prefix: false, assignment: false
+        }
+    }
+
+    const fn prefix(text: &'static str, kind: TokenType) -> Self {
+        Self {
+            text, kind, precedence: None, associativity: None, prefix: true, assignment: false
+        }
+    }
+
+    const fn postfix(text: &'static str, kind: TokenType) -> Self {
+        Self {
+            text, kind, precedence: None, associativity: None, prefix: false, assignment: false
+        }
+    }
+
+    const fn assignment(text: &'static str, kind: TokenType) -> Self {
+        Self {
+            text, kind, precedence: Some(1), associativity: Some(Associativity::Right), prefix: false, assignment: true
+        }
+    }
+
+    // `-` is both a binary and a prefix operator.
+    const fn and_prefix(mut self) -> Self {
+        self.prefix = true;
+        self
+    }
+}
+
+// Every operator the lexer knows, with how it parses. Punctuation lookup and the classification
+// methods on `TokenType` are all derived from this.
+const OPERATORS: &[Operator] = &[
+    Operator::assignment("=", TokenType::Assign),
+    Operator::assignment("+=", TokenType::PlusAssign),
+    Operator::assignment("-=", TokenType::MinusAssign),
+    Operator::assignment("*=", TokenType::MultiplyAssign),
+    Operator::assignment("/=", TokenType::DivideAssign),
+    Operator::assignment("%=", TokenType::ModulusAssign),
+    Operator::assignment("&=", TokenType::BitwiseAndAssign),
+    Operator::assignment("|=", TokenType::BitwiseOrAssign),
+    Operator::assignment("^=", TokenType::BitwiseXorAssign),
+    Operator::assignment("<<=", TokenType::BitwiseShiftLeftAssign),
+    Operator::assignment(">>=", TokenType::BitwiseShiftRightAssign),
+
+    Operator::binary("..", TokenType::Range, 2, Associativity::NonAssociative),
+    Operator::binary("..=", TokenType::RangeInclusive, 2, Associativity::NonAssociative),
+
+    Operator::binary("||", TokenType::LogicalOr, 3, Associativity::Left),
+    Operator::binary("&&", TokenType::LogicalAnd, 4, Associativity::Left),
+
+    Operator::binary("==", TokenType::Equal, 5, Associativity::NonAssociative),
+    Operator::binary("!=", TokenType::NotEqual, 5, Associativity::NonAssociative),
+    Operator::binary("<", TokenType::LessThan, 5, Associativity::NonAssociative),
+    Operator::binary(">", TokenType::GreaterThan, 5, Associativity::NonAssociative),
+    Operator::binary("<=", TokenType::LessThanEqual, 5, Associativity::NonAssociative),
+    Operator::binary(">=", TokenType::GreaterThanEqual, 5, Associativity::NonAssociative),
+
+    Operator::binary("|", TokenType::BitwiseOr, 6, Associativity::Left),
+    Operator::binary("^", TokenType::BitwiseXor, 7, Associativity::Left),
+    Operator::binary("&", TokenType::BitwiseAnd, 8, Associativity::Left),
+    Operator::binary("<<", TokenType::BitwiseShiftLeft, 9, Associativity::Left),
+    Operator::binary(">>", TokenType::BitwiseShiftRight, 9, Associativity::Left),
+
+    Operator::binary("+", TokenType::Plus, 10, Associativity::Left),
+    Operator::binary("-", TokenType::Minus, 10, Associativity::Left).and_prefix(),
+    Operator::binary("*", TokenType::Multiply, 11, Associativity::Left),
+    Operator::binary("/", TokenType::Divide, 11, Associativity::Left),
+    Operator::binary("%", TokenType::Modulus, 11, Associativity::Left),
+    Operator::binary("**", TokenType::Power, 12, Associativity::Right),
+
+    Operator::prefix("!", TokenType::LogicalNot),
+    Operator::prefix("~", TokenType::BitwiseNot),
+    Operator::postfix("?", TokenType::Question)
+];
+
+/// The payload of a token: the decoded value of a literal, or the source text of any other token.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum TokenValue {
     Bool(bool),
     Char(char),
+    /// A signed literal may be one past the largest value of its type, e.g. `128i8`, since that is
+    /// how the smallest value is written: `-128i8` is `-` applied to `128i8`. Whether it was negated
+    /// is up to the parser. `9223372036854775808` is stored as `i64::MIN`, so `wrapping_neg` gives the
+    /// negated value of every literal.
     Int(i64),
+    UInt(u64),
     Float(f64),
-    String(String)
+    String(String),
+    Null
 }
 
+/// The type suffix of a numeric literal, e.g. `u8` in `255u8`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum NumberSuffix {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64
+}
+
+impl NumberSuffix {
+    pub fn is_float(self) -> bool {
+        matches!(self, NumberSuffix::F32 | NumberSuffix::F64)
+    }
+
+    pub fn is_unsigned(self) -> bool {
+        matches!(self, NumberSuffix::U8 | NumberSuffix::U16 | NumberSuffix::U32 | NumberSuffix::U64)
+    }
+
+    // The largest literal of the type, which for signed types is the magnitude of the smallest value.
+    fn max_int(self) -> u128 {
+        match self {
+            NumberSuffix::I8 => i8::MIN.unsigned_abs() as u128,
+            NumberSuffix::I16 => i16::MIN.unsigned_abs() as u128,
+            NumberSuffix::I32 => i32::MIN.unsigned_abs() as u128,
+            NumberSuffix::I64 => i64::MIN.unsigned_abs() as u128,
+            NumberSuffix::U8 => u8::MAX as u128,
+            NumberSuffix::U16 => u16::MAX as u128,
+            NumberSuffix::U32 => u32::MAX as u128,
+            NumberSuffix::U64 => u64::MAX as u128,
+            NumberSuffix::F32 | NumberSuffix::F64 => u128::MAX
+        }
+    }
+}
+
+/// A token and where it came from.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Token {
     pub(crate) kind: TokenType,
     pub(crate) value: TokenValue,
-    line: u16
+    pub(crate) span: Span,
+    pub(crate) suffix: Option<NumberSuffix>,
+    // Only meaningful together with the symbol table it came from, so not serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) symbol: Option<Symbol>,
+    pub(crate) trivia: Vec<Trivia>
 }
 
 impl Token {
-    pub fn new(kind: TokenType, value: TokenValue, line: u16) -> Self {
+    pub fn new(kind: TokenType, value: TokenValue, span: Span) -> Self {
         Self {
-            kind, value, line
+            kind, value, span, suffix: None, symbol: None, trivia: Vec::new()
         }
     }
+
+    pub fn kind(&self) -> TokenType {
+        self.kind
+    }
+
+    /// The decoded value of a literal, or the source text of any other token.
+    pub fn value(&self) -> &TokenValue {
+        &self.value
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// The 1-based line the token starts on.
+    pub fn line(&self) -> u32 {
+        self.span.start.line
+    }
+
+    /// The keyword this identifier would be where a contextual keyword is allowed, e.g. `In` for `in`.
+    pub fn contextual_keyword(&self) -> Option<TokenType> {
+        match (&self.kind, &self.value) {
+            (TokenType::Identifier, TokenValue::String(name)) => contextual_keyword(name),
+            _ => None
+        }
+    }
+
+    /// The type suffix of a numeric literal, e.g. `U8` for `255u8`.
+    pub fn suffix(&self) -> Option<NumberSuffix> {
+        self.suffix
+    }
+
+    /// The interned name of an identifier or keyword.
+    pub fn symbol(&self) -> Option<Symbol> {
+        self.symbol
+    }
+
+    /// Comments that preceded this token, when the lexer was asked to keep them.
+    pub fn trivia(&self) -> &[Trivia] {
+        &self.trivia
+    }
+}
+
+/// The kind of a piece of trivia.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum TriviaKind {
+    ByteOrderMark,
+    Shebang,
+    Whitespace,
+    Newline,
+    LineComment,
+    BlockComment
+}
+
+/// Text between tokens kept for the token that follows it, see `LexerOptions`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trivia {
+    pub(crate) kind: TriviaKind,
+    pub(crate) span: Span
+}
+
+impl Trivia {
+    pub fn kind(&self) -> TriviaKind {
+        self.kind
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
 }
 
-pub struct Lexer {
-    path: String,
-    code: String,
+/// What the lexer keeps and reports. Built from `LexerOptions::default()`, since fields may be added.
+#[derive(Copy, Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct LexerOptions {
+    /// Attach ordinary comments to the following token as trivia instead of discarding them.
+    pub keep_comments: bool,
+    /// Keep whitespace, newlines and comments as trivia, so that the trivia and text of every token up
+    /// to and including `Eof` reproduce the source exactly.
+    pub lossless: bool,
+    /// Report warnings, such as confusable identifiers in untrusted code, as errors.
+    pub warnings_as_errors: bool
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Mode {
+    Normal,
+    // Inside the `{ ... }` of an interpolated string that opened at `start`. `braces` counts the
+    // unclosed `{` of the embedded expression, so only the matching `}` resumes the string.
+    Interpolation { start: Position, braces: u32 }
+}
+
+// Where a lexer is and which strings it is inside, so a lexer over another buffer of the same text
+// can carry on from there, or the same lexer can be rewound to it. Only the streaming lexer, which
+// needs `std`, uses it so far.
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+#[derive(Clone, Debug)]
+pub(crate) struct LexerState {
+    position: Position,
+    modes: Vec<Mode>,
+    errors: usize,
+    warnings: usize
+}
+
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+impl LexerState {
+    pub(crate) fn new() -> Self {
+        Self {
+            position: Position::new(0, 1, 1), modes: vec![Mode::Normal], errors: 0, warnings: 0
+        }
+    }
+
+    pub(crate) fn position(&self) -> Position {
+        self.position
+    }
+}
+
+/// Turns source text into tokens, collecting errors and warnings as it goes.
+pub struct Lexer<'src> {
+    file: FileId,
+    path: &'src str,
+    code: &'src str,
+    // Offset of `code` within the whole text, when only part of it is in memory.
+    base: usize,
     index: usize,
-    line: u16
-}
-
-lazy_static! {
-    static ref KEYWORD_MAP: HashMap<&'static str, TokenType> = {
-        let mut keyword_map = HashMap::new();
-        keyword_map.insert("let", TokenType::Let);
-        keyword_map.insert("const", TokenType::Const);
-        keyword_map.insert("enum", TokenType::Enum);
-        keyword_map.insert("struct", TokenType::Struct);
-        keyword_map.insert("fun", TokenType::Fun);
-
-        keyword_map.insert("if", TokenType::If);
-        keyword_map.insert("elif", TokenType::Elif);
-        keyword_map.insert("else", TokenType::Else);
-        keyword_map.insert("switch", TokenType::Switch);
-        keyword_map.insert("case", TokenType::Case);
-        keyword_map.insert("default", TokenType::Default);
-        keyword_map.insert("for", TokenType::For);
-        keyword_map.insert("while", TokenType::While);
-        keyword_map.insert("do", TokenType::Do);
-        keyword_map.insert("break", TokenType::Break);
-        keyword_map.insert("continue", TokenType::Continue);
-        keyword_map.insert("return", TokenType::Return);
-
-        keyword_map
-    };
+    line: u32,
+    column: u32,
+    errors: Vec<LexError>,
+    warnings: Vec<LexError>,
+    modes: Vec<Mode>,
+    options: LexerOptions,
+    symbols: SymbolTable,
+    identifiers: Identifiers,
+    // Set when a token was lexed the way it was because a scan found nothing more before the end of
+    // `code`, such as a char literal with neither a quote nor a line break after it. Bounded
+    // lookahead of a few characters does not count.
+    reached_end: bool,
+    finished: bool
+}
 
-    static ref OPERATOR_MAP: HashMap<&'static str, TokenType> = {
-        let mut operator_map = HashMap::new();
-        operator_map.insert("+", TokenType::Plus);
-        operator_map.insert("-", TokenType::Minus);
-        operator_map.insert("*", TokenType::Multiply);
-        operator_map.insert("/", TokenType::Divide);
-        operator_map.insert("%", TokenType::Modulus);
-
-        operator_map.insert("=", TokenType::Assign);
-
-        operator_map.insert("||", TokenType::LogicalOr);
-        operator_map.insert("!", TokenType::LogicalNot);
-        operator_map.insert("&&", TokenType::LogicalAnd);
-
-        operator_map.insert("<", TokenType::LessThan);
-        operator_map.insert(">", TokenType::GreaterThan);
-        operator_map.insert("==", TokenType::Equal);
-        operator_map.insert("!=", TokenType::NotEqual);
-        operator_map.insert("<=", TokenType::LessThanEqual);
-        operator_map.insert(">=", TokenType::GreaterThanEqual);
-
-        operator_map.insert("|", TokenType::BitwiseOr);
-        operator_map.insert("~", TokenType::BitwiseNot);
-        operator_map.insert("&", TokenType::BitwiseAnd);
-        operator_map.insert("^", TokenType::BitwiseXor);
-        operator_map.insert("<<", TokenType::BitwiseShiftLeft);
-        operator_map.insert(">>", TokenType::BitwiseShiftRight);
-
-        operator_map
-    };
+const MAX_PUNCTUATION_LENGTH: usize = 3;
 
-    static ref DELIMITER_MAP: HashMap<&'static str, TokenType> = {
-        let mut delimiter_map = HashMap::new();
-        delimiter_map.insert("(", TokenType::LeftParen);
-        delimiter_map.insert(")", TokenType::RightParen);
-        delimiter_map.insert("[", TokenType::LeftBracket);
-        delimiter_map.insert("]", TokenType::RightBracket);
-        delimiter_map.insert("{", TokenType::LeftBrace);
-        delimiter_map.insert("}", TokenType::RightBrace);
-        delimiter_map.insert(".", TokenType::Dot);
-        delimiter_map.insert(",", TokenType::Comma);
-        delimiter_map.insert(":", TokenType::Colon);
-        delimiter_map.insert(";", TokenType::SemiColon);
-
-        delimiter_map
-    };
+const KEYWORDS: &[(&str, TokenType)] = &[
+    ("let", TokenType::Let),
+    ("const", TokenType::Const),
+    ("enum", TokenType::Enum),
+    ("struct", TokenType::Struct),
+    ("fun", TokenType::Fun),
+    ("namespace", TokenType::Namespace),
+    ("import", TokenType::Import),
+    ("pub", TokenType::Pub),
+    ("impl", TokenType::Impl),
+    ("trait", TokenType::Trait),
+    ("self", TokenType::SelfValue),
+    ("mut", TokenType::Mut),
+
+    ("if", TokenType::If),
+    ("elif", TokenType::Elif),
+    ("else", TokenType::Else),
+    ("switch", TokenType::Switch),
+    ("case", TokenType::Case),
+    ("default", TokenType::Default),
+    ("for", TokenType::For),
+    ("while", TokenType::While),
+    ("do", TokenType::Do),
+    ("break", TokenType::Break),
+    ("continue", TokenType::Continue),
+    ("return", TokenType::Return),
+    ("match", TokenType::Match),
+    ("loop", TokenType::Loop),
 
-    static ref OPERATORS: &'static str = "+-*/%=<>|&^~";
-    static ref DELIMITERS: &'static str = "()[]{}.,:;";
+    ("true", TokenType::BoolVal),
+    ("false", TokenType::BoolVal),
+    ("null", TokenType::NullVal),
+    ("none", TokenType::NullVal)
+];
+
+// Words that are only keywords in certain positions, such as `in` after `for x`. The lexer
+// produces identifiers for them and the parser asks `Token::contextual_keyword` where it matters,
+// so scripts that use them as variable names keep working.
+const CONTEXTUAL_KEYWORDS: &[(&str, TokenType)] = &[
+    ("as", TokenType::As),
+    ("in", TokenType::In),
+    ("type", TokenType::Type)
+];
+
+const DELIMITERS: &[(&str, TokenType)] = &[
+    ("(", TokenType::LeftParen),
+    (")", TokenType::RightParen),
+    ("[", TokenType::LeftBracket),
+    ("]", TokenType::RightBracket),
+    ("{", TokenType::LeftBrace),
+    ("}", TokenType::RightBrace),
+    (".", TokenType::Dot),
+    (",", TokenType::Comma),
+    (":", TokenType::Colon),
+    ("::", TokenType::DoubleColon),
+    (";", TokenType::SemiColon),
+    ("->", TokenType::Arrow),
+    ("=>", TokenType::FatArrow),
+    ("...", TokenType::Ellipsis)
+];
+
+// The tables are small enough that a scan is about as fast as hashing, and needs no allocation or
+// lazy initialization, so it works without `std`.
+fn lookup(table: &[(&'static str, TokenType)], text: &str) -> Option<TokenType> {
+    table.iter().find(|(entry, _)| *entry == text).map(|(_, kind)| *kind)
+}
+
+fn operator(kind: TokenType) -> Option<&'static Operator> {
+    OPERATORS.iter().find(|operator| operator.kind == kind)
+}
+
+fn punctuation(text: &str) -> Option<TokenType> {
+    OPERATORS.iter().find(|operator| operator.text == text).map(|operator| operator.kind).or_else(|| lookup(DELIMITERS, text))
+}
+
+// The source text of every token type that always has the same text.
+fn token_text(kind: TokenType) -> Option<&'static str> {
+    let find = |table: &[(&'static str, TokenType)]| table.iter().find(|(_, entry)| *entry == kind).map(|(text, _)| *text);
+
+    operator(kind).map(|operator| operator.text)
+        .or_else(|| find(DELIMITERS))
+        .or_else(|| find(KEYWORDS).filter(|_| kind.is_keyword()))
+        .or_else(|| find(CONTEXTUAL_KEYWORDS))
 }
 
-impl Lexer {
-    pub fn new(path: String, code: String) -> Self {
+impl<'src> Lexer<'src> {
+    /// `path` is only used in diagnostics.
+    pub fn new(path: &'src str, code: &'src str) -> Self {
+        Self::with_file(FileId(0), path, code)
+    }
+
+    pub(crate) fn with_file(file: FileId, path: &'src str, code: &'src str) -> Self {
         Self {
-            path, code, index: 0, line: 1
+            file, path, code, base: 0, index: 0, line: 1, column: 1, errors: Vec::new(), warnings: Vec::new(), modes: vec![Mode::Normal], options: LexerOptions::default(), symbols: SymbolTable::new(), identifiers: Identifiers::default(), reached_end: false, finished: false
+        }
+    }
+
+    pub fn with_options(mut self, options: LexerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    // Starts lexing at `position` instead of the beginning of the code. The position must be a
+    // token boundary outside any string.
+    pub(crate) fn starting_at(mut self, position: Position) -> Self {
+        self.index = position.offset;
+        self.line = position.line;
+        self.column = position.column;
+        self
+    }
+
+    // Carries on from `state`, with `code` being the text from the state's position onwards.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn resuming(mut self, state: &LexerState) -> Self {
+        self.base = state.position.offset;
+        self.index = 0;
+        self.line = state.position.line;
+        self.column = state.position.column;
+        self.modes.clone_from(&state.modes);
+        self
+    }
+
+    // Carries on checking for confusable identifiers from those of the text before the starting
+    // position.
+    pub(crate) fn with_identifiers(mut self, identifiers: Identifiers) -> Self {
+        self.identifiers = identifiers;
+        self
+    }
+
+    pub(crate) fn identifiers(&self) -> &Identifiers {
+        &self.identifiers
+    }
+
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn take_identifiers(&mut self) -> Identifiers {
+        core::mem::take(&mut self.identifiers)
+    }
+
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn reached_end(&self) -> bool {
+        self.reached_end
+    }
+
+    // Reuses the allocation in `state`, since the streaming lexer saves one before every token.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn save_state(&self, state: &mut LexerState) {
+        state.position = self.current_position();
+        state.modes.clone_from(&self.modes);
+        state.errors = self.errors.len();
+        state.warnings = self.warnings.len();
+    }
+
+    // Rewinds to a state saved from this same lexer, dropping the diagnostics reported since.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn restore_state(&mut self, state: &LexerState) {
+        self.index = state.position.offset - self.base;
+        self.line = state.position.line;
+        self.column = state.position.column;
+        self.modes.clone_from(&state.modes);
+        self.errors.truncate(state.errors);
+        self.warnings.truncate(state.warnings);
+        self.identifiers.truncate(state.position.offset);
+        self.finished = false;
+    }
+
+    /// Interns names into `symbols` instead of a table of its own, e.g. one shared by every file of a compilation.
+    pub fn with_symbols(mut self, symbols: SymbolTable) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub fn path(&self) -> &'src str {
+        self.path
+    }
+
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    /// The errors so far, leaving none behind.
+    pub fn take_errors(&mut self) -> Vec<LexError> {
+        core::mem::take(&mut self.errors)
+    }
+
+    /// Diagnostics about code that lexes fine but is probably a mistake.
+    pub fn warnings(&self) -> &[LexError] {
+        &self.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<LexError> {
+        core::mem::take(&mut self.warnings)
+    }
+
+    fn error(&mut self, error: LexError) {
+        self.errors.push(error);
+    }
+
+    fn warning(&mut self, warning: LexError) {
+        if self.options.warnings_as_errors {
+            self.errors.push(warning);
+        } else {
+            self.warnings.push(warning);
         }
     }
 
-    fn advance(&mut self) {
-        if self.index < self.code.len() {
-            if self.code.chars().nth(self.index).unwrap() == '\n' {
-                self.line += 1;
+    pub(crate) fn current_position(&self) -> Position {
+        Position::new(self.base + self.index, self.line, self.column)
+    }
+
+    // Whether the lexer is outside every interpolated string, so that what it lexes next depends
+    // only on its position.
+    pub(crate) fn is_at_top_level(&self) -> bool {
+        self.modes.len() == 1
+    }
+
+    fn span_from(&self, start: Position) -> Span {
+        Span::new(self.file, start, self.current_position())
+    }
+
+    // Where `offset` is, given a position at or before it. Only for diagnostics, since it walks the text.
+    fn position_at(&self, start: Position, offset: usize) -> Position {
+        let text = &self.code[start.offset - self.base..offset - self.base];
+        let mut position = start;
+
+        for (index, c) in text.char_indices() {
+            if c == '\n' || (c == '\r' && !text[index + 1..].starts_with('\n')) {
+                position.line += 1;
+                position.column = 1;
+            } else {
+                position.column += 1;
             }
+        }
+
+        position.offset = offset;
+        position
+    }
+
+    // Warns about each character of the text since `start` that matches `predicate`. Each position
+    // is found from the previous one, so a long run of them is still walked only once.
+    fn check_characters(&mut self, start: Position, predicate: fn(char) -> bool, warning: fn(char, Span) -> LexError) {
+        let text = self.text_from(start);
 
-            self.index += 1;
+        if !text.contains(predicate) {
+            return;
         }
+
+        let mut position = start;
+
+        for (index, c) in text.char_indices().filter(|&(_, c)| predicate(c)) {
+            position = self.position_at(position, start.offset + index);
+            let end = Position::new(position.offset + c.len_utf8(), position.line, position.column + 1);
+            self.warning(warning(c, Span::new(self.file, position, end)));
+        }
+    }
+
+    // Bidirectional controls in strings and comments can make code look different from what it
+    // does (CVE-2021-42574, "Trojan Source").
+    fn check_bidi(&mut self, start: Position) {
+        self.check_characters(start, is_bidi_control, |character, span| LexError::BidiControl { character, span });
     }
 
-    fn get_identifier(&mut self) -> Token {
-        let mut start = self.index;
+    // Warns about an identifier that looks like a different one earlier in the text, e.g. `pаypal`
+    // spelled with a Cyrillic `а` after `paypal`. Like rustc's `confusable_idents`, only pairs with
+    // some non-ASCII are reported, since `rn` and `m` or `l` and `I` are ordinary code.
+    fn check_confusable(&mut self, identifier: &str, symbol: Symbol, start: Position) {
+        let key = self.symbols.skeleton_key(symbol);
 
-        while self.code.chars().nth(self.index).unwrap().is_alphabetic() {
-            self.advance();
+        if let Some(other) = self.identifiers.insert(key, symbol, start.offset) {
+            let other = self.symbols.resolve(other).unwrap_or_default();
+
+            if !identifier.is_ascii() || !other.is_ascii() {
+                self.warning(LexError::ConfusableIdentifier { name: identifier.to_string(), other: other.to_string(), span: self.span_from(start) });
+            }
         }
+    }
+
+    fn text_from(&self, start: Position) -> &'src str {
+        let code = self.code;
+        &code[start.offset - self.base..self.index]
+    }
+
+    fn token(&self, kind: TokenType, value: borrowed::TokenValue<'src>, start: Position) -> borrowed::Token<'src> {
+        borrowed::Token::new(kind, value, self.text_from(start), self.span_from(start))
+    }
 
-        let identifier = &self.code[start..self.index];
-        let kind = KEYWORD_MAP.get(identifier).cloned().unwrap_or(TokenType::Identifier);
-        Token::new(kind, TokenValue::String(identifier.to_string()), self.line)
+    fn peek(&self) -> Option<char> {
+        self.code[self.index..].chars().next()
     }
 
-    fn get_number(&mut self) -> Token {
-        let mut start = self.index;
+    fn peek2(&self) -> Option<char> {
+        let mut chars = self.code[self.index..].chars();
+        chars.next();
+        chars.next()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let current_char = self.peek()?;
+
+        // `\r\n`, `\n` and a lone `\r` each end a line.
+        if current_char == '\n' || (current_char == '\r' && self.peek2() != Some('\n')) {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        self.index += current_char.len_utf8();
+        Some(current_char)
+    }
 
-        while self.code.chars().nth(self.index).unwrap().is_digit(10) {
+    fn advance_while(&mut self, predicate: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(&predicate) {
             self.advance();
         }
+    }
+
+    fn get_identifier(&mut self) -> borrowed::Token<'src> {
+        let start = self.current_position();
+
+        self.advance();
+        self.advance_while(is_identifier_continue);
+
+        // Identifiers are compared in NFC, so `é` typed as one code point or as `e` plus a combining
+        // accent names the same thing.
+        let source = self.text_from(start);
+        let identifier = match is_nfc_quick(source.chars()) {
+            IsNormalized::Yes => Cow::Borrowed(source),
+            _ => Cow::Owned(source.nfc().collect::<String>())
+        };
+
+        if !identifier.is_ascii() {
+            if !identifier.as_ref().is_single_script() {
+                self.warning(LexError::MixedScriptIdentifier { span: self.span_from(start) });
+            }
+
+            self.check_characters(start, is_invisible, |character, span| LexError::InvisibleCharacter { character, span });
+        }
+
+        let kind = lookup(KEYWORDS, &identifier).unwrap_or(TokenType::Identifier);
+        let symbol = self.symbols.intern(&identifier);
+
+        if matches!(kind, TokenType::Identifier) {
+            self.check_confusable(&identifier, symbol, start);
+        }
+
+        let value = match kind {
+            TokenType::BoolVal => borrowed::TokenValue::Bool(identifier == "true"),
+            TokenType::NullVal => borrowed::TokenValue::Null,
+            TokenType::Identifier => borrowed::TokenValue::Str(identifier),
+            _ => borrowed::TokenValue::None
+        };
+
+        let mut token = self.token(kind, value, start);
+        token.symbol = Some(symbol);
+        token
+    }
+
+    // Numbers are a radix prefix (`0x`, `0o`, `0b`), digits with optional `_` separators, a fraction
+    // and exponent for decimal literals, and finally an optional type suffix such as `u8` or `f32`.
+    fn get_number(&mut self) -> borrowed::Token<'src> {
+        let start = self.current_position();
+
+        let radix = match (self.peek(), self.peek2()) {
+            (Some('0'), Some('x')) => 16,
+            (Some('0'), Some('o')) => 8,
+            (Some('0'), Some('b')) => 2,
+            _ => 10
+        };
 
-        if self.code.chars().nth(self.index).unwrap() == '.' {
+        if radix != 10 {
             self.advance();
+            self.advance();
+        }
+
+        let digits_start = self.index;
+        let mut is_float = false;
+
+        if radix == 16 {
+            self.advance_while(|c| c.is_ascii_hexdigit() || c == '_');
+        } else {
+            self.advance_while(|c| c.is_ascii_digit() || c == '_');
+        }
+
+        if radix == 10 {
+            if self.peek() == Some('.') && self.peek2().is_some_and(|c| c.is_ascii_digit()) {
+                self.advance();
+                self.advance_while(|c| c.is_ascii_digit() || c == '_');
+                is_float = true;
+            }
 
-            while self.code.chars().nth(self.index).unwrap().is_digit(10) {
+            if self.exponent_follows() {
                 self.advance();
+                if matches!(self.peek(), Some('+' | '-')) {
+                    self.advance();
+                }
+                self.advance_while(|c| c.is_ascii_digit() || c == '_');
+                is_float = true;
+            }
+        }
+
+        let digits = &self.code[digits_start..self.index];
+        let digits = if digits.contains('_') { Cow::Owned(digits.replace('_', "")) } else { Cow::Borrowed(digits) };
+
+        let suffix_start = self.index;
+        self.advance_while(is_identifier_continue);
+        let suffix_text = &self.code[suffix_start..self.index];
+
+        let suffix = match number_suffix(suffix_text) {
+            Some(suffix) if suffix.is_float() || !is_float => Some(suffix),
+            _ if suffix_text.is_empty() => None,
+            _ => {
+                // A number running straight into other letters (`12abc`, `1.5u8`) is one malformed literal.
+                self.error(LexError::MalformedNumber { span: self.span_from(start) });
+                None
             }
+        };
+
+        let mut token = if is_float || suffix.is_some_and(NumberSuffix::is_float) {
+            let value = self.get_float_value(start, &digits, radix, suffix);
+            self.token(TokenType::FloatVal, value, start)
+        } else {
+            let value = self.get_int_value(start, &digits, radix, suffix);
+            self.token(TokenType::IntVal, value, start)
+        };
+
+        token.suffix = suffix;
+        token
+    }
+
+    // `e` only starts an exponent when digits follow it, optionally after a sign.
+    fn exponent_follows(&self) -> bool {
+        let mut chars = self.code[self.index..].chars();
 
-            let number = &self.code[start..self.index];
-            return Token::new(TokenType::FloatVal, TokenValue::Float(number.parse::<f64>().unwrap()), self.line);
+        if !matches!(chars.next(), Some('e' | 'E')) {
+            return false;
         }
 
-        let number = &self.code[start..self.index];
-        Token::new(TokenType::IntVal, TokenValue::Int(number.parse::<i64>().unwrap()), self.line)
+        match chars.next() {
+            Some('+' | '-') => chars.next().is_some_and(|c| c.is_ascii_digit()),
+            next => next.is_some_and(|c| c.is_ascii_digit())
+        }
     }
 
-    fn get_string(&mut self) -> Token {
+    fn get_int_value(&mut self, start: Position, digits: &str, radix: u32, suffix: Option<NumberSuffix>) -> borrowed::TokenValue<'src> {
+        let value = match u128::from_str_radix(digits, radix) {
+            Ok(value) => value,
+            Err(error) => {
+                if digits.is_empty() || *error.kind() != IntErrorKind::PosOverflow {
+                    self.error(LexError::MalformedNumber { span: self.span_from(start) });
+                } else {
+                    self.error(LexError::Overflow { span: self.span_from(start) });
+                }
+                return if suffix.is_some_and(NumberSuffix::is_unsigned) { borrowed::TokenValue::UInt(0) } else { borrowed::TokenValue::Int(0) };
+            }
+        };
+
+        let max = suffix.unwrap_or(NumberSuffix::I64).max_int();
+        let fits = value <= max;
+
+        if !fits {
+            self.error(LexError::Overflow { span: self.span_from(start) });
+        }
+
+        match suffix {
+            Some(suffix) if suffix.is_unsigned() => borrowed::TokenValue::UInt(if fits { value as u64 } else { 0 }),
+            // Wraps the magnitude of `i64::MIN` around to `i64::MIN` itself.
+            _ => borrowed::TokenValue::Int(if fits { value as i64 } else { 0 })
+        }
+    }
+
+    fn get_float_value(&mut self, start: Position, digits: &str, radix: u32, suffix: Option<NumberSuffix>) -> borrowed::TokenValue<'src> {
+        if radix != 10 {
+            self.error(LexError::MalformedNumber { span: self.span_from(start) });
+            return borrowed::TokenValue::Float(0.0);
+        }
+
+        let value = digits.parse::<f64>().unwrap_or(0.0);
+
+        let fits = match suffix {
+            Some(NumberSuffix::F32) => (value as f32).is_finite(),
+            _ => value.is_finite()
+        };
+
+        if !fits {
+            self.error(LexError::Overflow { span: self.span_from(start) });
+            return borrowed::TokenValue::Float(0.0);
+        }
+
+        borrowed::TokenValue::Float(value)
+    }
+
+    fn get_string(&mut self) -> borrowed::Token<'src> {
+        let start = self.current_position();
+
         self.advance(); // Skip opening quote
 
-        let mut start = self.index;
+        self.get_string_segment(start, start, TokenType::StringVal, TokenType::StringStart)
+    }
 
-        while self.code.chars().nth(self.index).unwrap() != '"' {
-            self.advance();
+    // Resumes an interpolated string at the `}` closing its embedded expression.
+    fn get_string_continuation(&mut self, string_start: Position) -> borrowed::Token<'src> {
+        let start = self.current_position();
+
+        self.modes.pop();
+        self.advance(); // Skip closing brace
+
+        self.get_string_segment(start, string_start, TokenType::StringEnd, TokenType::StringPart)
+    }
+
+    // Reads string text up to the closing quote, producing `closed`, or up to an unescaped `{`,
+    // producing `interpolated` and entering interpolation mode for the embedded expression.
+    fn get_string_segment(&mut self, start: Position, string_start: Position, closed: TokenType, interpolated: TokenType) -> borrowed::Token<'src> {
+        let code = self.code;
+        let content = self.index;
+
+        // The text is borrowed from the source until the first escape, which needs a decoded copy.
+        let mut decoded: Option<String> = None;
+
+        let (kind, end) = loop {
+            let end = self.index;
+
+            match self.peek() {
+                None => {
+                    self.error(LexError::UnterminatedString { span: self.span_from(string_start) });
+                    break (closed, end);
+                }
+                Some('"') => {
+                    self.advance();
+                    break (closed, end);
+                }
+                Some('{') => {
+                    self.advance();
+                    self.modes.push(Mode::Interpolation { start: string_start, braces: 0 });
+                    break (interpolated, end);
+                }
+                Some('\\') => {
+                    let string = decoded.get_or_insert_with(|| code[content..end].to_string());
+                    if let Some(escaped) = self.get_escape() {
+                        string.push(escaped);
+                    }
+                }
+                Some(_) => {
+                    let current_char = self.advance().unwrap();
+                    if let Some(string) = &mut decoded {
+                        string.push(current_char);
+                    }
+                }
+            }
+        };
+
+        let string = match decoded {
+            Some(string) => Cow::Owned(string),
+            None => Cow::Borrowed(&code[content..end])
+        };
+
+        self.check_bidi(start);
+        self.token(kind, borrowed::TokenValue::Str(string), start)
+    }
+
+    // Number of `#`s between the `r` and the opening quote of a raw string, or `None` if the
+    // cursor is not on one.
+    fn raw_string_hashes(&mut self) -> Option<usize> {
+        let rest = self.code[self.index..].strip_prefix('r')?;
+        let hashes = rest.len() - rest.trim_start_matches('#').len();
+        self.reached_end |= hashes == rest.len();
+
+        rest[hashes..].starts_with('"').then_some(hashes)
+    }
+
+    // `r"C:\path"` or `r#"say "hi""#`: no escapes, closed by a quote followed by the same number of `#`s.
+    fn get_raw_string(&mut self, hashes: usize) -> borrowed::Token<'src> {
+        let start = self.current_position();
+
+        for _ in 0..hashes + 2 {
+            self.advance(); // Skip `r`, hashes and opening quote
         }
 
-        let string = &self.code[start..self.index];
-        Token::new(TokenType::StringVal, TokenValue::String(string.to_string()), self.line)
+        let code = self.code;
+        let content = self.index;
+        let terminator = format!("\"{}", "#".repeat(hashes));
+
+        let string = match code[content..].find(&terminator) {
+            Some(length) => {
+                while self.index < content + length + terminator.len() {
+                    self.advance();
+                }
+                &code[content..content + length]
+            }
+            None => {
+                self.advance_while(|_| true);
+                self.error(LexError::UnterminatedString { span: self.span_from(start) });
+                &code[content..]
+            }
+        };
+
+        self.check_bidi(start);
+        self.token(TokenType::StringVal, borrowed::TokenValue::Str(Cow::Borrowed(string)), start)
     }
 
-    fn get_operator(&mut self) -> Token {
-        let mut start = self.index;
+    fn get_char(&mut self) -> borrowed::Token<'src> {
+        let start = self.current_position();
 
-        while OPERATORS.contains(self.code.chars().nth(self.index).unwrap()) {
-            self.advance();
+        self.advance(); // Skip opening quote
+
+        let value = match self.peek() {
+            Some('\'') => {
+                self.advance();
+                self.error(LexError::EmptyChar { span: self.span_from(start) });
+                return self.token(TokenType::CharVal, borrowed::TokenValue::Char('\0'), start);
+            }
+            None | Some('\n' | '\r') => {
+                self.error(LexError::UnterminatedChar { span: self.span_from(start) });
+                return self.token(TokenType::CharVal, borrowed::TokenValue::Char('\0'), start);
+            }
+            Some('\\') => self.get_escape().unwrap_or(char::REPLACEMENT_CHARACTER),
+            Some(_) => self.advance().unwrap()
+        };
+
+        if self.peek() != Some('\'') {
+            // `'ab'` is reported as a single over-long literal; a quote with no partner on the line is
+            // unterminated. Only the text up to the next quote or line break decides which.
+            let rest = &self.code[self.index..];
+            let end = rest.find(|c| c == '\'' || is_line_break(c));
+            self.reached_end |= end.is_none();
+
+            if end.is_some_and(|end| rest[end..].starts_with('\'')) {
+                self.advance_while(|c| c != '\'');
+                self.advance();
+                self.error(LexError::MultipleChars { span: self.span_from(start) });
+            } else {
+                self.error(LexError::UnterminatedChar { span: self.span_from(start) });
+            }
+
+            self.check_bidi(start);
+            return self.token(TokenType::CharVal, borrowed::TokenValue::Char(value), start);
         }
 
-        let operator = &self.code[start..self.index];
-
-        let kind = match operator {
-            "+" => TokenType::Plus,
-            "-" => TokenType::Minus,
-            "*" => TokenType::Multiply,
-            "/" => TokenType::Divide,
-            "%" => TokenType::Modulus,
-            "=" => TokenType::Assign,
-            "==" => TokenType::Equal,
-            "!=" => TokenType::NotEqual,
-            "<" => TokenType::LessThan,
-            ">" => TokenType::GreaterThan,
-            "<=" => TokenType::LessThanEqual,
-            ">=" => TokenType::GreaterThanEqual,
-            "||" => TokenType::LogicalOr,
-            "!" => TokenType::LogicalNot,
-            "&&" => TokenType::LogicalAnd,
-            "|" => TokenType::BitwiseOr,
-            "~" => TokenType::BitwiseNot,
-            "&" => TokenType::BitwiseAnd,
-            "^" => TokenType::BitwiseXor,
-            "<<" => TokenType::BitwiseShiftLeft,
-            ">>" => TokenType::BitwiseShiftRight,
-            _ => unreachable!()
+        self.advance(); // Skip closing quote
+
+        self.check_bidi(start);
+        self.token(TokenType::CharVal, borrowed::TokenValue::Char(value), start)
+    }
+
+    // Decodes the escape sequence at the cursor, which must be on the backslash.
+    fn get_escape(&mut self) -> Option<char> {
+        let start = self.current_position();
+
+        self.advance(); // Skip backslash
+
+        let value = match self.advance() {
+            Some('n') => Some('\n'),
+            Some('t') => Some('\t'),
+            Some('r') => Some('\r'),
+            Some('0') => Some('\0'),
+            Some('\\') => Some('\\'),
+            Some('\'') => Some('\''),
+            Some('"') => Some('"'),
+            Some('{') => Some('{'),
+            Some('}') => Some('}'),
+            Some('x') => self.get_hex_escape(),
+            Some('u') => self.get_unicode_escape(),
+            _ => None
         };
 
-        Token::new(kind, TokenValue::String(operator.to_string()), self.line)
+        if value.is_none() {
+            let sequence = self.text_from(start).to_string();
+            self.error(LexError::InvalidEscape { sequence, span: self.span_from(start) });
+        }
+
+        value
     }
 
-    fn get_delimiter(&mut self) -> Token {
-        let mut start = self.index;
+    // `\x41`: exactly two hex digits, limited to ASCII.
+    fn get_hex_escape(&mut self) -> Option<char> {
+        let digits_start = self.index;
 
-        while DELIMITERS.contains(self.code.chars().nth(self.index).unwrap()) {
+        for _ in 0..2 {
+            if !self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
             self.advance();
         }
 
-        let delimiter = &self.code[start..self.index];
-
-        let kind = match delimiter {
-            "(" => TokenType::LeftParen,
-            ")" => TokenType::RightParen,
-            "[" => TokenType::LeftBracket,
-            "]" => TokenType::RightBracket,
-            "{" => TokenType::LeftBrace,
-            "}" => TokenType::RightBrace,
-            "." => TokenType::Dot,
-            "," => TokenType::Comma,
-            ":" => TokenType::Colon,
-            ";" => TokenType::SemiColon,
-            _ => unreachable!()
-        };
+        let value = u8::from_str_radix(&self.code[digits_start..self.index], 16).ok()?;
+        value.is_ascii().then_some(value as char)
+    }
 
-        Token::new(kind, TokenValue::String(delimiter.to_string()), self.line)
+    // `\u{1F600}`: one to six hex digits naming a Unicode scalar value.
+    fn get_unicode_escape(&mut self) -> Option<char> {
+        if self.peek() != Some('{') {
+            return None;
+        }
+        self.advance();
+
+        let digits_start = self.index;
+        self.advance_while(|c| c.is_ascii_hexdigit());
+        let digits = &self.code[digits_start..self.index];
+
+        if self.peek() != Some('}') {
+            return None;
+        }
+        self.advance();
+
+        if digits.is_empty() || digits.len() > 6 {
+            return None;
+        }
+
+        u32::from_str_radix(digits, 16).ok().and_then(char::from_u32)
     }
 
-    pub fn next_token(&mut self) -> Token {
-        if self.index >= self.code.len() {
-            return Token::new(TokenType::Eof, TokenValue::String("".to_string()), self.line);
+    // Maximal munch: the longest operator or delimiter at the cursor wins, so `<<=` is one token
+    // while `=-` is `=` followed by `-`.
+    fn get_punctuation(&mut self) -> Option<borrowed::Token<'src>> {
+        let start = self.current_position();
+        let rest = &self.code[self.index..];
+
+        let (punctuation, kind) = (1..=MAX_PUNCTUATION_LENGTH).rev().find_map(|length| {
+            let candidate = rest.get(..length)?;
+            Some((candidate, punctuation(candidate)?))
+        })?;
+
+        for _ in punctuation.chars() {
+            self.advance();
         }
 
-        let current_char = self.code.chars().nth(self.index).unwrap();
+        Some(self.token(kind, borrowed::TokenValue::None, start))
+    }
+
+    // Skips whitespace and ordinary comments, stopping in front of doc comments since those are tokens.
+    // A byte order mark and a `#!` interpreter line are skipped too, but only at the start of the file.
+    fn skip_trivia(&mut self) -> Vec<Trivia> {
+        let mut trivia = Vec::new();
 
-        if current_char.is_whitespace() {
+        loop {
+            let start = self.current_position();
+            let rest = &self.code[self.index..];
+
+            let kind = if start.offset == 0 && rest.starts_with('\u{FEFF}') {
+                self.advance();
+                self.column = 1;
+                TriviaKind::ByteOrderMark
+            } else if start.line == 1 && start.column == 1 && rest.starts_with("#!") {
+                self.advance_while(|c| !is_line_break(c));
+                TriviaKind::Shebang
+            } else if rest.starts_with(is_line_break) {
+                if rest.starts_with("\r\n") {
+                    self.advance();
+                }
+                self.advance();
+                TriviaKind::Newline
+            } else if rest.starts_with(char::is_whitespace) {
+                self.skip_whitespace();
+                TriviaKind::Whitespace
+            } else if rest.starts_with("//") {
+                if is_doc_comment(rest) {
+                    break;
+                }
+
+                self.advance_while(|c| !is_line_break(c));
+                TriviaKind::LineComment
+            } else if rest.starts_with("/*") {
+                self.skip_block_comment();
+                TriviaKind::BlockComment
+            } else {
+                break;
+            };
+
+            if matches!(kind, TriviaKind::LineComment | TriviaKind::BlockComment) {
+                self.check_bidi(start);
+            }
+
+            let keep = match kind {
+                TriviaKind::ByteOrderMark | TriviaKind::Whitespace | TriviaKind::Newline => self.options.lossless,
+                TriviaKind::Shebang | TriviaKind::LineComment | TriviaKind::BlockComment => self.options.keep_comments || self.options.lossless
+            };
+
+            if keep {
+                trivia.push(Trivia { kind, span: self.span_from(start) });
+            }
+        }
+
+        trivia
+    }
+
+    // A run of whitespace up to, but not including, the next line break.
+    fn skip_whitespace(&mut self) {
+        while let Some(current_char) = self.peek() {
+            if is_line_break(current_char) || !current_char.is_whitespace() {
+                break;
+            }
+            self.advance();
+        }
+    }
+
+    // Block comments nest, so `/* a /* b */ c */` is a single comment.
+    fn skip_block_comment(&mut self) {
+        let start = self.current_position();
+        let mut depth = 0;
+
+        loop {
+            let rest = &self.code[self.index..];
+
+            if rest.starts_with("/*") {
+                depth += 1;
+            } else if rest.starts_with("*/") {
+                depth -= 1;
+            } else if self.advance().is_some() {
+                continue;
+            } else {
+                self.error(LexError::UnterminatedComment { span: self.span_from(start) });
+                return;
+            }
+
+            self.advance();
             self.advance();
-            return self.next_token();
+
+            if depth == 0 {
+                return;
+            }
+        }
+    }
+
+    fn get_doc_comment(&mut self) -> borrowed::Token<'src> {
+        let start = self.current_position();
+
+        let kind = if self.code[self.index..].starts_with("//!") { TokenType::InnerDocComment } else { TokenType::DocComment };
+
+        for _ in 0..3 {
+            self.advance(); // Skip `///` or `//!`
+        }
+
+        let code = self.code;
+        let content = self.index;
+        self.advance_while(|c| !is_line_break(c));
+        let text = &code[content..self.index];
+
+        self.check_bidi(start);
+        self.token(kind, borrowed::TokenValue::Str(Cow::Borrowed(text)), start)
+    }
+
+    /// The next token. Past the end of the input, keeps returning `Eof`.
+    pub fn next_token(&mut self) -> Token {
+        self.next_borrowed().into_owned()
+    }
+
+    /// Like `next_token`, but identifier and string payloads borrow from the source where possible and
+    /// punctuation carries no payload, so lexing allocates next to nothing.
+    pub fn next_borrowed(&mut self) -> borrowed::Token<'src> {
+        let trivia = self.skip_trivia();
+
+        let mut token = self.lex_token();
+        token.trivia = trivia;
+        token
+    }
+
+    /// The zero-copy counterpart of iterating the lexer: every token including the final `Eof`, then stops.
+    pub fn borrowed_tokens(&mut self) -> impl Iterator<Item = borrowed::Token<'src>> + '_ {
+        core::iter::from_fn(move || {
+            if self.finished {
+                return None;
+            }
+
+            let token = self.next_borrowed();
+            self.finished = matches!(token.kind, TokenType::Eof);
+            Some(token)
+        })
+    }
+
+    fn lex_token(&mut self) -> borrowed::Token<'src> {
+        let start = self.current_position();
+
+        let Some(current_char) = self.peek() else {
+            while let Some(Mode::Interpolation { start: string_start, .. }) = self.modes.pop() {
+                self.error(LexError::UnterminatedString { span: self.span_from(string_start) });
+            }
+            self.modes.push(Mode::Normal);
+
+            return self.token(TokenType::Eof, borrowed::TokenValue::None, start);
+        };
+
+        if let Some(Mode::Interpolation { start: string_start, braces }) = self.modes.last_mut() {
+            match current_char {
+                '{' => *braces += 1,
+                '}' if *braces == 0 => {
+                    let string_start = *string_start;
+                    return self.get_string_continuation(string_start);
+                }
+                '}' => *braces -= 1,
+                _ => {}
+            }
+        }
+
+        if let Some(hashes) = self.raw_string_hashes() {
+            return self.get_raw_string(hashes);
         }
 
-        if current_char.is_alphabetic() {
+        if is_identifier_start(current_char) {
             return self.get_identifier();
         }
 
-        if current_char.is_digit(10) {
+        if current_char.is_ascii_digit() {
             return self.get_number();
         }
 
@@ -330,14 +1433,100 @@ impl Lexer {
             return self.get_string();
         }
 
-        if OPERATORS.contains(current_char) {
-            return self.get_operator();
+        if current_char == '\'' {
+            return self.get_char();
+        }
+
+        if is_doc_comment(&self.code[self.index..]) {
+            return self.get_doc_comment();
         }
 
-        if DELIMITERS.contains(current_char) {
-            return self.get_delimiter();
+        if let Some(token) = self.get_punctuation() {
+            return token;
         }
 
-        Token::new(TokenType::Error, TokenValue::String(format!("Unexpected character: {}", current_char)), self.line)
+        self.advance();
+
+        self.error(LexError::UnexpectedCharacter { character: current_char, span: self.span_from(start) });
+
+        self.token(TokenType::Error, borrowed::TokenValue::None, start)
     }
+}
+
+/// Yields every token including the final `Eof`, then stops.
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if self.finished {
+            return None;
+        }
+
+        let token = self.next_token();
+        self.finished = matches!(token.kind, TokenType::Eof);
+        Some(token)
+    }
+}
+
+/// Lexes `source` in one go, returning every token including the final `Eof`, and the errors.
+pub fn tokenize(source: &str) -> (Vec<Token>, Vec<LexError>) {
+    let mut lexer = Lexer::new("", source);
+    let tokens = lexer.by_ref().collect();
+    (tokens, lexer.take_errors())
+}
+
+// Identifiers follow Unicode UAX #31, with `_` also allowed as a first character.
+fn is_identifier_start(c: char) -> bool {
+    c == '_' || unicode_ident::is_xid_start(c)
+}
+
+fn is_identifier_continue(c: char) -> bool {
+    unicode_ident::is_xid_continue(c)
+}
+
+/// Keywords and the literal words `true`, `false`, `null` and `none` can never name anything.
+pub fn is_reserved(word: &str) -> bool {
+    lookup(KEYWORDS, word).is_some()
+}
+
+pub(crate) fn contextual_keyword(word: &str) -> Option<TokenType> {
+    lookup(CONTEXTUAL_KEYWORDS, word)
+}
+
+fn number_suffix(suffix: &str) -> Option<NumberSuffix> {
+    let suffix = match suffix {
+        "i8" => NumberSuffix::I8,
+        "i16" => NumberSuffix::I16,
+        "i32" => NumberSuffix::I32,
+        "i64" => NumberSuffix::I64,
+        "u8" => NumberSuffix::U8,
+        "u16" => NumberSuffix::U16,
+        "u32" => NumberSuffix::U32,
+        "u64" => NumberSuffix::U64,
+        "f32" => NumberSuffix::F32,
+        "f64" => NumberSuffix::F64,
+        _ => return None
+    };
+
+    Some(suffix)
+}
+
+// The explicit directional embeddings, overrides and isolates, and the implicit marks.
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+// Characters that render as nothing, several of which UAX #31 allows in identifiers, so that two
+// names that look identical can differ.
+fn is_invisible(c: char) -> bool {
+    matches!(c, '\u{00AD}' | '\u{034F}' | '\u{115F}' | '\u{1160}' | '\u{180B}'..='\u{180F}' | '\u{200B}'..='\u{200D}' | '\u{2060}'..='\u{2064}' | '\u{3164}' | '\u{FE00}'..='\u{FE0F}' | '\u{FEFF}' | '\u{FFA0}' | '\u{E0100}'..='\u{E01EF}')
+}
+
+pub(crate) fn is_line_break(c: char) -> bool {
+    c == '\n' || c == '\r'
+}
+
+// `///` and `//!` start doc comments, but `////...` is an ordinary comment.
+fn is_doc_comment(text: &str) -> bool {
+    text.starts_with("//!") || (text.starts_with("///") && !text.starts_with("////"))
 }
\ No newline at end of file
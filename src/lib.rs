@@ -0,0 +1,1345 @@
+//! A lexer for the Ja scripting language.
+//!
+//! Everything public is re-exported from the crate root, except the zero-copy tokens in
+//! [`borrowed`].
+//!
+//! # Stability
+//!
+//! The crate follows semantic versioning. Before 1.0, a minor release (0.x to 0.y) may make
+//! breaking changes and a patch release may not. These are not breaking changes:
+//!
+//! - adding variants to the enums, which are all `#[non_exhaustive]`, such as new token types,
+//!   errors or trivia kinds;
+//! - adding fields to `LexerOptions`, which is `#[non_exhaustive]` and so is built from
+//!   `LexerOptions::default()`;
+//! - adding methods, functions and types;
+//! - reporting new warnings, or errors for input that was never valid.
+//!
+//! Token fields are private and read through accessor methods, so their representation can
+//! change. The tests in `tests/public_api.rs` pin down the public API and must keep compiling
+//! and passing across non-breaking releases.
+//!
+//! # `no_std`
+//!
+//! Without the default `std` feature the crate only needs `core` and `alloc`. `StreamLexer`, which
+//! reads from `std::io`, is then unavailable.
+//!
+//! # `serde`
+//!
+//! The `serde` feature derives `Serialize` and `Deserialize` for tokens, spans and errors. Their
+//! JSON form is part of the stable API and pinned down by `tests/serde.rs`. Symbols are left out,
+//! since they only mean something together with their `SymbolTable`.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod borrowed;
+mod cursor;
+mod encoding;
+mod error;
+mod incremental;
+mod lexer;
+mod source;
+#[cfg(feature = "std")]
+mod stream;
+mod symbol;
+
+pub use cursor::{Checkpoint, TokenCursor};
+pub use encoding::{decode, DecodedSource, Encoding, OffsetMap};
+pub use error::LexError;
+pub use incremental::IncrementalLexer;
+pub use lexer::{is_reserved, tokenize, Associativity, Lexer, LexerOptions, NumberSuffix, Token, TokenType, TokenValue, Trivia, TriviaKind};
+pub use source::{FileId, Location, Position, SourceFile, SourceMap, Span};
+#[cfg(feature = "std")]
+pub use stream::StreamLexer;
+pub use symbol::{Symbol, SymbolTable};
+
+#[cfg(test)]
+mod tests {
+    #![allow(unused, warnings)]
+    use std::borrow::Cow;
+    use crate::borrowed;
+    use crate::cursor::TokenCursor;
+    use crate::encoding::{decode, Encoding};
+    use crate::error::LexError;
+    use crate::incremental::IncrementalLexer;
+    use crate::lexer::{is_reserved, tokenize, Associativity, Lexer, LexerOptions, NumberSuffix, Token, TokenType, TokenValue, TriviaKind};
+    use crate::source::{FileId, Position, SourceMap, Span};
+    #[cfg(feature = "std")]
+    use crate::stream::StreamLexer;
+    use crate::symbol::{Symbol, SymbolTable};
+    use super::*;
+
+    impl PartialEq for TokenValue {
+        fn eq(&self, other: &Self) -> bool {
+            match (self, other) {
+                (TokenValue::Bool(a), TokenValue::Bool(b)) => a == b,
+                (TokenValue::Char(a), TokenValue::Char(b)) => a == b,
+                (TokenValue::Int(a), TokenValue::Int(b)) => a == b,
+                (TokenValue::UInt(a), TokenValue::UInt(b)) => a == b,
+                (TokenValue::Float(a), TokenValue::Float(b)) => a == b,
+                (TokenValue::String(a), TokenValue::String(b)) => a == b,
+                (TokenValue::Null, TokenValue::Null) => true,
+                _ => false, // Different variants are not equal
+            }
+        }
+    }
+
+
+    impl PartialEq for Token {
+        fn eq(&self, other: &Self) -> bool {
+            self.kind == other.kind && self.value == other.value
+        }
+    }
+
+    fn token(kind: TokenType, value: TokenValue, line: u32) -> Token {
+        let position = Position::new(0, line, 1);
+        Token::new(kind, value, Span::new(FileId(0), position, position))
+    }
+
+
+    #[test]
+    fn test_lexer_identifiers() {
+        let code = "let x = 10;
+                   const y = 'a';
+                   enum Color { Red, Green, Blue };
+                   struct Point { x: i32, y: i32 };
+                   fun add(a: i32, b: i32) -> i32 { a + b };
+                   namespace MyNamespace { ... };";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::Let, TokenValue::String("let".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("x".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Assign, TokenValue::String("=".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::IntVal, TokenValue::Int(10), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::SemiColon, TokenValue::String(";".to_string()), 1));
+
+        assert_eq!(lexer.next_token(), token(TokenType::Const, TokenValue::String("const".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("y".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::Assign, TokenValue::String("=".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::CharVal, TokenValue::Char('a'), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::SemiColon, TokenValue::String(";".to_string()), 2));
+
+        assert_eq!(lexer.next_token(), token(TokenType::Enum, TokenValue::String("enum".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("Color".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::LeftBrace, TokenValue::String("{".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("Red".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::Comma, TokenValue::String(",".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("Green".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::Comma, TokenValue::String(",".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("Blue".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::RightBrace, TokenValue::String("}".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::SemiColon, TokenValue::String(";".to_string()), 3));
+
+        assert_eq!(lexer.next_token(), token(TokenType::Struct, TokenValue::String("struct".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("Point".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::LeftBrace, TokenValue::String("{".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("x".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::Colon, TokenValue::String(":".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("i32".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::Comma, TokenValue::String(",".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("y".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::Colon, TokenValue::String(":".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("i32".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::RightBrace, TokenValue::String("}".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::SemiColon, TokenValue::String(";".to_string()), 4));
+
+        assert_eq!(lexer.next_token(), token(TokenType::Fun, TokenValue::String("fun".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("add".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::LeftParen, TokenValue::String("(".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("a".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::Colon, TokenValue::String(":".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("i32".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::Comma, TokenValue::String(",".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("b".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::Colon, TokenValue::String(":".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("i32".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::RightParen, TokenValue::String(")".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::Arrow, TokenValue::String("->".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("i32".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::LeftBrace, TokenValue::String("{".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("a".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::Plus, TokenValue::String("+".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("b".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::RightBrace, TokenValue::String("}".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::SemiColon, TokenValue::String(";".to_string()), 5));
+
+        assert_eq!(lexer.next_token(), token(TokenType::Namespace, TokenValue::String("namespace".to_string()), 6));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("MyNamespace".to_string()), 6));
+        assert_eq!(lexer.next_token(), token(TokenType::LeftBrace, TokenValue::String("{".to_string()), 6));
+        assert_eq!(lexer.next_token(), token(TokenType::Ellipsis, TokenValue::String("...".to_string()), 6));
+        assert_eq!(lexer.next_token(), token(TokenType::RightBrace, TokenValue::String("}".to_string()), 6));
+        assert_eq!(lexer.next_token(), token(TokenType::SemiColon, TokenValue::String(";".to_string()), 6));
+    }
+
+    #[test]
+    fn test_lexer_unicode_identifiers() {
+        let code = "my_var i32 point2d _unused _ αβγ 変数名 cafe\u{301} caf\u{e9}";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        for expected in ["my_var", "i32", "point2d", "_unused", "_", "αβγ", "変数名", "caf\u{e9}", "caf\u{e9}"] {
+            assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String(expected.to_string()), 1));
+        }
+        assert_eq!(lexer.next_token().kind, TokenType::Eof);
+        assert!(lexer.errors().is_empty());
+        assert!(lexer.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_lexer_mixed_script_identifiers() {
+        // The second `a` in `pаypal` is CYRILLIC SMALL LETTER A.
+        let code = "paypal p\u{430}ypal";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("paypal".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("p\u{430}ypal".to_string()), 1));
+
+        assert!(lexer.errors().is_empty());
+        assert!(matches!(lexer.warnings(), [LexError::MixedScriptIdentifier { .. }, LexError::ConfusableIdentifier { .. }]));
+        assert_eq!(lexer.warnings()[0].span().start().offset(), 7);
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn test_lexer_numbers() {
+        let code = "10
+                   1234567890
+                   3.14159
+                   0.001
+                   1.0";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::IntVal, TokenValue::Int(10), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::IntVal, TokenValue::Int(1234567890), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::FloatVal, TokenValue::Float(3.14159), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::FloatVal, TokenValue::Float(0.001), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::FloatVal, TokenValue::Float(1.0), 5));
+    }
+
+    #[test]
+    fn test_lexer_number_formats() {
+        let code = "0xFF 0o755 0b1010 1_000_000 6.02e23 1e-9 2E+3 0x_dead_BEEF";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::IntVal, TokenValue::Int(255), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::IntVal, TokenValue::Int(0o755), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::IntVal, TokenValue::Int(10), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::IntVal, TokenValue::Int(1_000_000), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::FloatVal, TokenValue::Float(6.02e23), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::FloatVal, TokenValue::Float(1e-9), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::FloatVal, TokenValue::Float(2000.0), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::IntVal, TokenValue::Int(0xdead_beef), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Eof, TokenValue::String("".to_string()), 1));
+        assert!(lexer.errors().is_empty());
+    }
+
+    #[test]
+    fn test_lexer_number_suffixes() {
+        let code = "10i32 255u8 1.5f32 7f64 0xFFu16 18446744073709551615u64";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        let expected = [
+            (TokenType::IntVal, TokenValue::Int(10), NumberSuffix::I32),
+            (TokenType::IntVal, TokenValue::UInt(255), NumberSuffix::U8),
+            (TokenType::FloatVal, TokenValue::Float(1.5), NumberSuffix::F32),
+            (TokenType::FloatVal, TokenValue::Float(7.0), NumberSuffix::F64),
+            (TokenType::IntVal, TokenValue::UInt(255), NumberSuffix::U16),
+            (TokenType::IntVal, TokenValue::UInt(u64::MAX), NumberSuffix::U64)
+        ];
+
+        for (kind, value, suffix) in expected {
+            let number = lexer.next_token();
+            assert_eq!(number, token(kind, value, 1));
+            assert_eq!(number.suffix(), Some(suffix));
+        }
+        assert!(lexer.errors().is_empty());
+
+        assert_eq!(Lexer::new("test.txt", "42").next_token().suffix(), None);
+    }
+
+    #[test]
+    fn test_lexer_number_errors() {
+        let code = "256u8 129i8 9223372036854775809 1e400 1e39f32 0b102 0x 1.5u8 12abc 340282366920938463463374607431768211456";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        while lexer.next_token().kind != TokenType::Eof {}
+
+        let errors = lexer.errors();
+        assert_eq!(errors.len(), 10);
+        for error in &errors[..5] {
+            assert!(matches!(error, LexError::Overflow { .. }), "{:?}", error);
+        }
+        for error in &errors[5..9] {
+            assert!(matches!(error, LexError::MalformedNumber { .. }), "{:?}", error);
+        }
+        assert!(matches!(errors[9], LexError::Overflow { .. }));
+
+        let span = errors[0].span();
+        assert_eq!(&code[span.start().offset()..span.end().offset()], "256u8");
+
+        // The smallest signed values are written as the negation of a literal one past the largest.
+        let (tokens, errors) = tokenize("-128i8 -9223372036854775808");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(tokens.iter().map(|token| token.kind).collect::<Vec<_>>(), [TokenType::Minus, TokenType::IntVal, TokenType::Minus, TokenType::IntVal, TokenType::Eof]);
+        assert!(matches!(tokens[1].value, TokenValue::Int(value) if value.wrapping_neg() as i8 == i8::MIN));
+        assert_eq!(tokens[1].suffix, Some(NumberSuffix::I8));
+        assert!(matches!(tokens[3].value, TokenValue::Int(value) if value.wrapping_neg() == i64::MIN));
+    }
+
+    #[test]
+    fn test_lexer_strings() {
+        let code = "\"Hello, world!\"
+                   \"This is a string with spaces.\"";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::StringVal, TokenValue::String("Hello, world!".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::StringVal, TokenValue::String("This is a string with spaces.".to_string()), 2));
+    }
+
+    #[test]
+    fn test_lexer_chars() {
+        let code = r"'a' 'ß' '\n' '\t' '\\' '\'' '\0' '\x41' '\u{1F600}'";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        for expected in ['a', 'ß', '\n', '\t', '\\', '\'', '\0', 'A', '😀'] {
+            assert_eq!(lexer.next_token(), token(TokenType::CharVal, TokenValue::Char(expected), 1));
+        }
+        assert_eq!(lexer.next_token(), token(TokenType::Eof, TokenValue::String("".to_string()), 1));
+        assert!(lexer.errors().is_empty());
+    }
+
+    #[test]
+    fn test_lexer_char_errors() {
+        let code = r"'' 'ab' '\q' '\x80' '\u{110000}' 'a";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        for _ in 0..6 {
+            assert_eq!(lexer.next_token().kind, TokenType::CharVal);
+        }
+        assert_eq!(lexer.next_token().kind, TokenType::Eof);
+
+        let errors = lexer.errors();
+        assert_eq!(errors.len(), 6);
+        assert!(matches!(errors[0], LexError::EmptyChar { .. }));
+        assert!(matches!(errors[1], LexError::MultipleChars { .. }));
+        assert!(matches!(&errors[2], LexError::InvalidEscape { sequence, .. } if sequence == r"\q"));
+        assert!(matches!(&errors[3], LexError::InvalidEscape { sequence, .. } if sequence == r"\x80"));
+        assert!(matches!(&errors[4], LexError::InvalidEscape { sequence, .. } if sequence == r"\u{110000}"));
+        assert!(matches!(errors[5], LexError::UnterminatedChar { .. }));
+
+        let span = errors[1].span();
+        assert_eq!(&code[span.start().offset()..span.end().offset()], "'ab'");
+
+        // Each literal only looks as far as its own closing quote, however long the line is.
+        let (tokens, errors) = tokenize(&"'ab' ".repeat(100_000));
+        assert_eq!(tokens.len(), 100_001);
+        assert!(errors.len() == 100_000 && errors.iter().all(|error| matches!(error, LexError::MultipleChars { .. })));
+    }
+
+    #[test]
+    fn test_lexer_string_escapes() {
+        let code = r#""tab\there" "quote \"q\" \\ back" "\u{48}\x49\0" "bad \q escape""#;
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::StringVal, TokenValue::String("tab\there".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::StringVal, TokenValue::String("quote \"q\" \\ back".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::StringVal, TokenValue::String("HI\0".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::StringVal, TokenValue::String("bad  escape".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Eof, TokenValue::String("".to_string()), 1));
+
+        assert_eq!(lexer.errors().len(), 1);
+        assert!(matches!(&lexer.errors()[0], LexError::InvalidEscape { sequence, .. } if sequence == r"\q"));
+    }
+
+    #[test]
+    fn test_lexer_raw_strings() {
+        let code = r####"r"C:\temp\new" r#"say "hi" \n"# r##"a "# b"## raw"####;
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::StringVal, TokenValue::String(r"C:\temp\new".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::StringVal, TokenValue::String(r#"say "hi" \n"#.to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::StringVal, TokenValue::String(r##"a "# b"##.to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("raw".to_string()), 1));
+        assert!(lexer.errors().is_empty());
+
+        let mut lexer = Lexer::new("test.txt", r##"r#"never closed"##);
+        assert_eq!(lexer.next_token().kind, TokenType::StringVal);
+        assert!(matches!(lexer.errors(), [LexError::UnterminatedString { .. }]));
+    }
+
+    #[test]
+    fn test_lexer_multiline_strings() {
+        let code = "\"first\nsecond\" after\nr\"x\ny\" last";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        let string = lexer.next_token();
+        assert_eq!(string, token(TokenType::StringVal, TokenValue::String("first\nsecond".to_string()), 1));
+        assert_eq!((string.span().start().line(), string.span().end().line()), (1, 2));
+        assert_eq!(string.span().end().column(), 8);
+
+        let after = lexer.next_token();
+        assert_eq!((after.span().start().line(), after.span().start().column()), (2, 9));
+
+        let raw = lexer.next_token();
+        assert_eq!(raw, token(TokenType::StringVal, TokenValue::String("x\ny".to_string()), 3));
+        assert_eq!(raw.span().start().line(), 3);
+
+        let last = lexer.next_token();
+        assert_eq!((last.span().start().line(), last.span().start().column()), (4, 4));
+    }
+
+    #[test]
+    fn test_lexer_string_interpolation() {
+        let code = r#""Hello {name}, you have {count + 1} items" "plain \{braces\}""#;
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::StringStart, TokenValue::String("Hello ".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("name".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::StringPart, TokenValue::String(", you have ".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("count".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Plus, TokenValue::String("+".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::IntVal, TokenValue::Int(1), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::StringEnd, TokenValue::String(" items".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::StringVal, TokenValue::String("plain {braces}".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Eof, TokenValue::String("".to_string()), 1));
+        assert!(lexer.errors().is_empty());
+    }
+
+    #[test]
+    fn test_lexer_nested_interpolation() {
+        let code = r#""a {f({x: "b {y}"})} c""#;
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        let expected = [
+            (TokenType::StringStart, "a "),
+            (TokenType::Identifier, "f"),
+            (TokenType::LeftParen, "("),
+            (TokenType::LeftBrace, "{"),
+            (TokenType::Identifier, "x"),
+            (TokenType::Colon, ":"),
+            (TokenType::StringStart, "b "),
+            (TokenType::Identifier, "y"),
+            (TokenType::StringEnd, ""),
+            (TokenType::RightBrace, "}"),
+            (TokenType::RightParen, ")"),
+            (TokenType::StringEnd, " c")
+        ];
+
+        for (kind, text) in expected {
+            assert_eq!(lexer.next_token(), token(kind, TokenValue::String(text.to_string()), 1));
+        }
+        assert_eq!(lexer.next_token().kind, TokenType::Eof);
+        assert!(lexer.errors().is_empty());
+
+        let mut lexer = Lexer::new("test.txt", r#""open {x"#);
+        while lexer.next_token().kind != TokenType::Eof {}
+        assert!(matches!(lexer.errors(), [LexError::UnterminatedString { .. }]));
+        assert_eq!(lexer.errors()[0].span().start().offset(), 0);
+    }
+
+    #[test]
+    fn test_lexer_comments() {
+        let code = "//! Geometry helpers.
+                   a / b // divide
+                   /* outer /* inner */ still outer */ c
+                   //// not a doc comment
+                   /// Adds two numbers.
+                   fun add";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::InnerDocComment, TokenValue::String(" Geometry helpers.".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("a".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::Divide, TokenValue::String("/".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("b".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("c".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::DocComment, TokenValue::String(" Adds two numbers.".to_string()), 5));
+        assert_eq!(lexer.next_token(), token(TokenType::Fun, TokenValue::String("fun".to_string()), 6));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("add".to_string()), 6));
+        assert_eq!(lexer.next_token(), token(TokenType::Eof, TokenValue::String("".to_string()), 6));
+        assert!(lexer.errors().is_empty());
+
+        let mut lexer = Lexer::new("test.txt", "a /* never /* closed */");
+        lexer.next_token();
+        assert_eq!(lexer.next_token().kind, TokenType::Eof);
+        assert!(matches!(lexer.errors(), [LexError::UnterminatedComment { .. }]));
+    }
+
+    #[test]
+    fn test_lexer_comment_trivia() {
+        let code = "// leading\n/* block */ x /* trailing */";
+
+        let mut lexer = Lexer::new("test.txt", code).with_options(LexerOptions { keep_comments: true, ..LexerOptions::default() });
+
+        let x = lexer.next_token();
+        assert_eq!(x, token(TokenType::Identifier, TokenValue::String("x".to_string()), 2));
+        assert_eq!(x.trivia().len(), 2);
+        assert_eq!(x.trivia()[0].kind(), TriviaKind::LineComment);
+        assert_eq!(x.trivia()[1].kind(), TriviaKind::BlockComment);
+
+        let span = x.trivia()[0].span();
+        assert_eq!(&code[span.start().offset()..span.end().offset()], "// leading");
+
+        let eof = lexer.next_token();
+        assert_eq!(eof.kind, TokenType::Eof);
+        assert_eq!(eof.trivia()[0].kind(), TriviaKind::BlockComment);
+
+        let mut lexer = Lexer::new("test.txt", code);
+        assert!(lexer.next_token().trivia().is_empty());
+    }
+
+    #[test]
+    fn test_lexer_operators() {
+        let code = "+ - * / % =
+                   == != < > <= >=
+                   || ! &&
+                   | ~ & ^ << >>";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::Plus, TokenValue::String("+".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Minus, TokenValue::String("-".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Multiply, TokenValue::String("*".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Divide, TokenValue::String("/".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Modulus, TokenValue::String("%".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Assign, TokenValue::String("=".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Equal, TokenValue::String("==".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::NotEqual, TokenValue::String("!=".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::LessThan, TokenValue::String("<".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::GreaterThan, TokenValue::String(">".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::LessThanEqual, TokenValue::String("<=".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::GreaterThanEqual, TokenValue::String(">=".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::LogicalOr, TokenValue::String("||".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::LogicalNot, TokenValue::String("!".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::LogicalAnd, TokenValue::String("&&".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::BitwiseOr, TokenValue::String("|".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::BitwiseNot, TokenValue::String("~".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::BitwiseAnd, TokenValue::String("&".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::BitwiseXor, TokenValue::String("^".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::BitwiseShiftLeft, TokenValue::String("<<".to_string()), 4));
+        assert_eq!(lexer.next_token(), token(TokenType::BitwiseShiftRight, TokenValue::String(">>".to_string()), 4));
+    }
+
+    #[test]
+    fn test_lexer_compound_operators() {
+        let code = "+= -= *= /= %= &= |= ^= <<= >>=
+                   -> => :: .. ..= ... ? **
+                   a<<=b x..=y f()";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        let expected = [
+            (TokenType::PlusAssign, "+="),
+            (TokenType::MinusAssign, "-="),
+            (TokenType::MultiplyAssign, "*="),
+            (TokenType::DivideAssign, "/="),
+            (TokenType::ModulusAssign, "%="),
+            (TokenType::BitwiseAndAssign, "&="),
+            (TokenType::BitwiseOrAssign, "|="),
+            (TokenType::BitwiseXorAssign, "^="),
+            (TokenType::BitwiseShiftLeftAssign, "<<="),
+            (TokenType::BitwiseShiftRightAssign, ">>="),
+            (TokenType::Arrow, "->"),
+            (TokenType::FatArrow, "=>"),
+            (TokenType::DoubleColon, "::"),
+            (TokenType::Range, ".."),
+            (TokenType::RangeInclusive, "..="),
+            (TokenType::Ellipsis, "..."),
+            (TokenType::Question, "?"),
+            (TokenType::Power, "**"),
+            (TokenType::Identifier, "a"),
+            (TokenType::BitwiseShiftLeftAssign, "<<="),
+            (TokenType::Identifier, "b"),
+            (TokenType::Identifier, "x"),
+            (TokenType::RangeInclusive, "..="),
+            (TokenType::Identifier, "y"),
+            (TokenType::Identifier, "f"),
+            (TokenType::LeftParen, "("),
+            (TokenType::RightParen, ")")
+        ];
+
+        for (kind, text) in expected {
+            assert_eq!(lexer.next_token(), token(kind, TokenValue::String(text.to_string()), 1));
+        }
+        assert_eq!(lexer.next_token().kind, TokenType::Eof);
+        assert!(lexer.errors().is_empty());
+
+        let mut lexer = Lexer::new("test.txt", "1..10");
+        assert_eq!(lexer.next_token(), token(TokenType::IntVal, TokenValue::Int(1), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Range, TokenValue::String("..".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::IntVal, TokenValue::Int(10), 1));
+    }
+
+    #[test]
+    fn test_lexer_delimiters() {
+        let code = "( ) [ ] { } . , : ;";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::LeftParen, TokenValue::String("(".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::RightParen, TokenValue::String(")".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::LeftBracket, TokenValue::String("[".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::RightBracket, TokenValue::String("]".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::LeftBrace, TokenValue::String("{".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::RightBrace, TokenValue::String("}".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Dot, TokenValue::String(".".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Comma, TokenValue::String(",".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Colon, TokenValue::String(":".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::SemiColon, TokenValue::String(";".to_string()), 1));
+    }
+
+    #[test]
+    fn test_lexer_keywords() {
+        let code = "let const enum struct fun
+                   if elif else switch case default
+                   for while do break continue return
+                   namespace";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::Let, TokenValue::String("let".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Const, TokenValue::String("const".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Enum, TokenValue::String("enum".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Struct, TokenValue::String("struct".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Fun, TokenValue::String("fun".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::If, TokenValue::String("if".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::Elif, TokenValue::String("elif".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::Else, TokenValue::String("else".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::Switch, TokenValue::String("switch".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::Case, TokenValue::String("case".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::Default, TokenValue::String("default".to_string()), 2));
+        assert_eq!(lexer.next_token(), token(TokenType::For, TokenValue::String("for".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::While, TokenValue::String("while".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::Do, TokenValue::String("do".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::Break, TokenValue::String("break".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::Continue, TokenValue::String("continue".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::Return, TokenValue::String("return".to_string()), 3));
+        assert_eq!(lexer.next_token(), token(TokenType::Namespace, TokenValue::String("namespace".to_string()), 4));
+    }
+
+    #[test]
+    fn test_lexer_extended_keywords() {
+        let code = "import pub impl trait self mut match loop namespace";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        let expected = [
+            (TokenType::Import, "import"),
+            (TokenType::Pub, "pub"),
+            (TokenType::Impl, "impl"),
+            (TokenType::Trait, "trait"),
+            (TokenType::SelfValue, "self"),
+            (TokenType::Mut, "mut"),
+            (TokenType::Match, "match"),
+            (TokenType::Loop, "loop"),
+            (TokenType::Namespace, "namespace")
+        ];
+
+        for (kind, text) in expected {
+            assert_eq!(lexer.next_token(), token(kind, TokenValue::String(text.to_string()), 1));
+            assert!(is_reserved(text));
+        }
+    }
+
+    #[test]
+    fn test_lexer_contextual_keywords() {
+        let code = "for x in items { let type = x as i32; let in = type; }";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        let mut contextual = Vec::new();
+        loop {
+            let token = lexer.next_token();
+            match token.kind {
+                TokenType::Eof => break,
+                TokenType::Identifier => contextual.push(token.contextual_keyword()),
+                _ => {}
+            }
+        }
+
+        assert_eq!(contextual, vec![
+            None,
+            Some(TokenType::In),
+            None,
+            Some(TokenType::Type),
+            None,
+            Some(TokenType::As),
+            None,
+            Some(TokenType::In),
+            Some(TokenType::Type)
+        ]);
+
+        for word in ["as", "in", "type"] {
+            assert!(!is_reserved(word));
+        }
+    }
+
+    #[test]
+    fn test_lexer_literal_keywords() {
+        let code = "true false null none truth nullable";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::BoolVal, TokenValue::Bool(true), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::BoolVal, TokenValue::Bool(false), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::NullVal, TokenValue::Null, 1));
+        assert_eq!(lexer.next_token(), token(TokenType::NullVal, TokenValue::Null, 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("truth".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("nullable".to_string()), 1));
+
+        for word in ["true", "false", "null", "none", "let", "return"] {
+            assert!(is_reserved(word), "{}", word);
+        }
+        assert!(!is_reserved("truth"));
+    }
+
+    #[test]
+    fn test_lexer_eof() {
+        let code = "";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::Eof, TokenValue::String("".to_string()), 1));
+    }
+
+    #[test]
+    fn test_lexer_error() {
+        let code = "1 + 2 * $";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::IntVal, TokenValue::Int(1), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Plus, TokenValue::String("+".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::IntVal, TokenValue::Int(2), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Multiply, TokenValue::String("*".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Error, TokenValue::String("$".to_string()), 1));
+    }
+
+    #[test]
+    fn test_lexer_error_recovery() {
+        let code = "let a = 99999999999999999999;
+                   let b = 12ab @ 3;
+                   let c = \"never closed";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        let mut kinds = Vec::new();
+        loop {
+            let token = lexer.next_token();
+            if let TokenType::Eof = token.kind {
+                break;
+            }
+            kinds.push(token.kind);
+        }
+
+        assert_eq!(kinds.len(), 16);
+        assert_eq!(kinds[9], TokenType::Error);
+        assert_eq!(kinds[15], TokenType::StringVal);
+
+        let errors = lexer.errors();
+        assert_eq!(errors.len(), 4);
+        assert!(matches!(errors[0], LexError::Overflow { .. }));
+        assert!(matches!(errors[1], LexError::MalformedNumber { .. }));
+        assert!(matches!(errors[2], LexError::UnexpectedCharacter { character: '@', .. }));
+        assert!(matches!(errors[3], LexError::UnterminatedString { .. }));
+
+        let span = errors[1].span();
+        assert_eq!(&code[span.start().offset()..span.end().offset()], "12ab");
+        assert_eq!((span.start().line(), span.start().column()), (2, 28));
+        assert_eq!(errors[2].to_string(), "unexpected character `@`");
+    }
+
+    #[test]
+    fn test_lexer_operator_runs() {
+        let code = "a=-1 x<-y";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("a".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Assign, TokenValue::String("=".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Minus, TokenValue::String("-".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::IntVal, TokenValue::Int(1), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("x".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::LessThan, TokenValue::String("<".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Minus, TokenValue::String("-".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("y".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Eof, TokenValue::String("".to_string()), 1));
+        assert!(lexer.errors().is_empty());
+    }
+
+    #[test]
+    fn test_lexer_iterator() {
+        let mut lexer = Lexer::new("test.txt", "let x = 1;");
+
+        let kinds = lexer.by_ref().map(|token| token.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![TokenType::Let, TokenType::Identifier, TokenType::Assign, TokenType::IntVal, TokenType::SemiColon, TokenType::Eof]);
+        assert!(lexer.next().is_none());
+
+        let (tokens, errors) = tokenize("a $ b");
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokens[1], token(TokenType::Error, TokenValue::String("$".to_string()), 1));
+        assert_eq!(tokens[3].kind, TokenType::Eof);
+        assert!(matches!(errors[..], [LexError::UnexpectedCharacter { character: '$', .. }]));
+    }
+
+    #[test]
+    fn test_token_cursor() {
+        let mut cursor = TokenCursor::new(Lexer::new("test.txt", "a + b;"));
+
+        assert_eq!(cursor.peek_nth(3).kind, TokenType::SemiColon);
+        assert_eq!(cursor.peek().kind, TokenType::Identifier);
+
+        let checkpoint = cursor.checkpoint();
+        assert_eq!(cursor.next().unwrap().kind, TokenType::Identifier);
+        assert_eq!(cursor.next().unwrap().kind, TokenType::Plus);
+
+        cursor.rewind(checkpoint);
+        assert_eq!(cursor.next().unwrap(), token(TokenType::Identifier, TokenValue::String("a".to_string()), 1));
+
+        assert_eq!(cursor.peek_nth(100).kind, TokenType::Eof);
+        assert_eq!(cursor.by_ref().count(), 4);
+        assert!(cursor.is_at_end());
+        assert!(cursor.next().is_none());
+    }
+
+    #[test]
+    fn test_token_cursor_exhausted_lexer() {
+        let mut lexer = Lexer::new("test.txt", "a");
+        lexer.by_ref().for_each(drop);
+
+        let mut cursor = TokenCursor::new(lexer);
+        assert_eq!(cursor.peek().kind, TokenType::Eof);
+        assert!(cursor.is_at_end());
+        assert_eq!(cursor.next().unwrap().kind, TokenType::Eof);
+        assert!(cursor.next().is_none());
+    }
+
+    #[test]
+    fn test_lexer_spans() {
+        let code = "let x = 10;\n  foo;";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        let span = lexer.next_token().span();
+        assert_eq!((span.start().offset(), span.end().offset()), (0, 3));
+        assert_eq!((span.start().line(), span.start().column()), (1, 1));
+        assert_eq!((span.end().line(), span.end().column()), (1, 4));
+
+        let span = lexer.next_token().span();
+        assert_eq!((span.start().offset(), span.start().column()), (4, 5));
+
+        lexer.next_token();
+        let span = lexer.next_token().span();
+        assert_eq!((span.start().offset(), span.end().offset()), (8, 10));
+        assert_eq!((span.start().column(), span.end().column()), (9, 11));
+
+        lexer.next_token();
+        let span = lexer.next_token().span();
+        assert_eq!((span.start().offset(), span.end().offset()), (14, 17));
+        assert_eq!((span.start().line(), span.start().column()), (2, 3));
+    }
+
+    #[test]
+    fn test_lexer_non_ascii() {
+        let code = "let größe = 1.5; ünïcode";
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        assert_eq!(lexer.next_token(), token(TokenType::Let, TokenValue::String("let".to_string()), 1));
+
+        let identifier = lexer.next_token();
+        assert_eq!(identifier, token(TokenType::Identifier, TokenValue::String("größe".to_string()), 1));
+        assert_eq!((identifier.span().start().offset(), identifier.span().end().offset()), (4, 11));
+        assert_eq!((identifier.span().start().column(), identifier.span().end().column()), (5, 10));
+
+        assert_eq!(lexer.next_token(), token(TokenType::Assign, TokenValue::String("=".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::FloatVal, TokenValue::Float(1.5), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::SemiColon, TokenValue::String(";".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Identifier, TokenValue::String("ünïcode".to_string()), 1));
+        assert_eq!(lexer.next_token(), token(TokenType::Eof, TokenValue::String("".to_string()), 1));
+    }
+
+    #[test]
+    fn test_source_map_locations() {
+        let mut sources = SourceMap::new();
+        sources.add_file("first.ja", "let a = 1;");
+        let file = sources.add_file("second.ja", "let b = 2;\nlet c = b + 1;");
+
+        let mut lexer = sources.lexer(file);
+        for _ in 0..5 {
+            lexer.next_token();
+        }
+
+        let token = lexer.next_token();
+        assert_eq!(token.span().file(), file);
+        assert_eq!(sources.location(token.span()).to_string(), "second.ja:2:1");
+        assert_eq!(sources.source_text(token.span()), "let");
+
+        let plus = (0..4).map(|_| lexer.next_token()).last().unwrap();
+        assert_eq!(sources.location(plus.span()).to_string(), "second.ja:2:11");
+        assert_eq!(sources.snippet(plus.span()), "2 | let c = b + 1;\n  |           ^");
+    }
+
+    #[test]
+    fn test_lexer_borrowed_tokens() {
+        let code = r#"let name = "plain" + "esc\tape"; r"raw""#;
+
+        let mut lexer = Lexer::new("test.txt", code);
+
+        let keyword = lexer.next_borrowed();
+        assert_eq!((keyword.kind, &keyword.value, keyword.text()), (TokenType::Let, &borrowed::TokenValue::None, "let"));
+
+        let identifier = lexer.next_borrowed();
+        assert!(matches!(identifier.value, borrowed::TokenValue::Str(Cow::Borrowed("name"))));
+
+        let assign = lexer.next_borrowed();
+        assert_eq!((&assign.value, assign.text()), (&borrowed::TokenValue::None, "="));
+
+        let plain = lexer.next_borrowed();
+        assert!(matches!(plain.value, borrowed::TokenValue::Str(Cow::Borrowed("plain"))));
+        assert_eq!(plain.text(), "\"plain\"");
+
+        lexer.next_borrowed();
+
+        let escaped = lexer.next_borrowed();
+        assert!(matches!(&escaped.value, borrowed::TokenValue::Str(Cow::Owned(string)) if string == "esc\tape"));
+
+        lexer.next_borrowed();
+
+        let raw = lexer.next_borrowed();
+        assert!(matches!(raw.value, borrowed::TokenValue::Str(Cow::Borrowed("raw"))));
+
+        assert_eq!(lexer.next_borrowed().kind, TokenType::Eof);
+    }
+
+    #[test]
+    fn test_lexer_borrowed_matches_owned() {
+        let code = "fun f(x) { return \"{x}\" ** 2u8 != 'c' || none; } // done";
+
+        let owned = Lexer::new("test.txt", code).collect::<Vec<_>>();
+        let borrowed = Lexer::new("test.txt", code).borrowed_tokens().map(borrowed::Token::into_owned).collect::<Vec<_>>();
+
+        assert_eq!(borrowed.len(), owned.len());
+        for (borrowed, owned) in borrowed.iter().zip(&owned) {
+            assert_eq!(borrowed, owned);
+            assert_eq!(borrowed.span(), owned.span());
+        }
+    }
+
+    #[test]
+    fn test_lexer_symbols() {
+        let mut sources = SourceMap::new();
+        let first = sources.add_file("first.ja", "let count = count + total;");
+        let second = sources.add_file("second.ja", "fun total() { return count; }");
+
+        let first_tokens = sources.lexer(first).collect::<Vec<_>>();
+        let second_tokens = sources.lexer(second).collect::<Vec<_>>();
+
+        let count = first_tokens[1].symbol().unwrap();
+        assert_eq!(first_tokens[3].symbol(), Some(count));
+        assert_eq!(second_tokens[6].symbol(), Some(count));
+        assert_eq!(second_tokens[1].symbol(), first_tokens[5].symbol());
+        assert_ne!(first_tokens[5].symbol(), Some(count));
+
+        assert_eq!(first_tokens[0].symbol(), sources.symbols().get("let"));
+        assert_eq!(first_tokens[2].symbol(), None);
+        assert_eq!(sources.symbols().resolve(count).as_deref(), Some("count"));
+        assert_eq!(SymbolTable::new().resolve(count), None);
+    }
+
+    #[test]
+    fn test_lexer_lossless() {
+        let code = "//! Module docs\r\nlet  x\t= /* a /* nested */ note */ 1;\r\n\n// trailing\nsay(\"hi {x + 1}!\") $ 'c'\n  /// doc\n\"open {x";
+
+        let mut lexer = Lexer::new("test.txt", code).with_options(LexerOptions { lossless: true, ..LexerOptions::default() });
+        let tokens = lexer.by_ref().collect::<Vec<_>>();
+
+        let mut rebuilt = String::new();
+        for token in &tokens {
+            for trivia in token.trivia() {
+                rebuilt.push_str(&code[trivia.span().start().offset()..trivia.span().end().offset()]);
+            }
+            rebuilt.push_str(&code[token.span().start().offset()..token.span().end().offset()]);
+        }
+        assert_eq!(rebuilt, code);
+
+        let kinds = tokens[1].trivia().iter().map(|trivia| trivia.kind()).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![TriviaKind::Newline]);
+
+        let kinds = tokens[4].trivia().iter().map(|trivia| trivia.kind()).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![TriviaKind::Whitespace, TriviaKind::BlockComment, TriviaKind::Whitespace]);
+
+        let kinds = tokens[6].trivia().iter().map(|trivia| trivia.kind()).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![TriviaKind::Newline, TriviaKind::Newline, TriviaKind::LineComment, TriviaKind::Newline]);
+    }
+
+    // The incremental tokens, trivia and errors must match lexing the edited text from scratch.
+    fn assert_relexed(incremental: &IncrementalLexer) {
+        let mut lexer = Lexer::new("test.txt", incremental.text()).with_options(LexerOptions { lossless: true, ..LexerOptions::default() });
+        let tokens = lexer.by_ref().collect::<Vec<_>>();
+
+        assert_eq!(incremental.tokens().len(), tokens.len());
+        for (incremental, token) in incremental.tokens().iter().zip(&tokens) {
+            assert_eq!(incremental, token);
+            assert_eq!(incremental.span(), token.span());
+            assert_eq!(incremental.trivia(), token.trivia());
+        }
+        assert_eq!(incremental.errors(), lexer.errors());
+        assert_eq!(incremental.warnings(), lexer.warnings());
+    }
+
+    #[test]
+    fn test_incremental_lexer() {
+        let code = "let a = 1;\nlet b = \"two {a}\";\n/* note */\nlet c = a + b;\nlet d = 'd';\n";
+
+        let mut incremental = IncrementalLexer::new("test.txt", code).with_options(LexerOptions { lossless: true, ..LexerOptions::default() });
+
+        // Renaming within a line only re-lexes that line.
+        let relexed = incremental.edit(8..9, "100");
+        assert_relexed(&incremental);
+        assert_eq!(relexed, 0..6);
+
+        // Opening a string swallows everything after it, and closing it again resynchronizes.
+        incremental.edit(13..13, "\"");
+        assert_relexed(&incremental);
+        assert!(!incremental.errors().is_empty());
+        incremental.edit(13..14, "");
+        assert_relexed(&incremental);
+        assert!(incremental.errors().is_empty());
+
+        // Opening and closing a block comment across lines.
+        let comment = incremental.text().find("/*").unwrap();
+        incremental.edit(comment + 8..comment + 10, "");
+        assert_relexed(&incremental);
+        assert!(matches!(incremental.errors(), [LexError::UnterminatedComment { .. }]));
+        incremental.edit(comment + 8..comment + 8, "*/\n");
+        assert_relexed(&incremental);
+        assert!(incremental.errors().is_empty());
+
+        // Editing inside an interpolation and adding lines shifts the tokens after it.
+        let interpolation = incremental.text().find("{a}").unwrap();
+        incremental.edit(interpolation + 1..interpolation + 2, "a + \"{1}\"\n\n");
+        assert_relexed(&incremental);
+
+        incremental.edit(0..incremental.text().len(), "");
+        assert_relexed(&incremental);
+        assert_eq!(incremental.tokens().len(), 1);
+    }
+
+    #[test]
+    fn test_incremental_lexer_confusables() {
+        let is_confusable = |warning: &LexError| matches!(warning, LexError::ConfusableIdentifier { .. });
+        let options = LexerOptions { lossless: true, ..LexerOptions::default() };
+
+        // A name edited away is not reported when a look-alike is added later.
+        let mut incremental = IncrementalLexer::new("test.txt", "let paypal = 1;\n").with_options(options);
+        incremental.edit(4..10, "x");
+        let end = incremental.text().len();
+        incremental.edit(end..end, "let p\u{430}ypal = 2;\n");
+        assert_relexed(&incremental);
+        assert!(!incremental.warnings().iter().any(is_confusable));
+
+        // Adding or removing a name before the look-alike changes the warning on unchanged lines.
+        let mut incremental = IncrementalLexer::new("test.txt", "let x = 1;\nlet y = 2;\n\nlet p\u{430}ypal = 3;\n").with_options(options);
+        incremental.edit(4..5, "paypal");
+        assert_relexed(&incremental);
+        assert_eq!(incremental.warnings().iter().filter(|warning| is_confusable(warning)).count(), 1);
+
+        incremental.edit(4..10, "x");
+        assert_relexed(&incremental);
+        assert!(!incremental.warnings().iter().any(is_confusable));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_stream_lexer() {
+        let code = "let größe = 12_345.678e-2;\nlet s = \"a long string {größe + 1} that\nspans lines\";\n/* block\ncomment */ r#\"raw \"quoted\" text\"#\n'c' $ 0xFF;\n\"unterminated";
+        // Lookahead to the end of the line and past many `#`s, which a short chunk cuts off.
+        let code = &format!("let paypal = p\u{430}ypal;\nlet c = 'ab{}';\nlet r = r{}\"raw\"{};\n{}", " x".repeat(20), "#".repeat(20), "#".repeat(20), code);
+
+        let mut lexer = Lexer::new("test.txt", code);
+        let expected = lexer.by_ref().collect::<Vec<_>>();
+
+        // Chunks of a few bytes split tokens, UTF-8 sequences and lines in every possible place.
+        for chunk_size in [1, 2, 3, 7, 64 * 1024] {
+            let reader = std::io::BufReader::with_capacity(chunk_size, code.as_bytes());
+            let mut stream = StreamLexer::new("test.txt", reader).with_chunk_size(chunk_size);
+            let tokens = stream.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+
+            assert_eq!(tokens, expected);
+            assert_eq!(tokens.iter().map(Token::span).collect::<Vec<_>>(), expected.iter().map(Token::span).collect::<Vec<_>>());
+            assert_eq!(stream.errors(), lexer.errors());
+            assert_eq!(stream.warnings(), lexer.warnings());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_stream_lexer_single_line() {
+        use std::cell::Cell;
+        use std::io::{BufReader, Read};
+        use std::rc::Rc;
+
+        struct Counting<'a>(&'a [u8], Rc<Cell<usize>>);
+
+        impl Read for Counting<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let read = self.0.read(buf)?;
+                self.1.set(self.1.get() + read);
+                Ok(read)
+            }
+        }
+
+        // Generated data is often one long line, and a malformed literal at its start must not hold
+        // the rest of it back.
+        let cases = [
+            ("[1, 2.5, \"three\", {four: 'f'}], ".repeat(32 * 1024), TokenType::LeftBracket),
+            (format!("'ab' {}", "x + 1, ".repeat(64 * 1024)), TokenType::CharVal)
+        ];
+
+        for (code, first) in cases {
+            let read = Rc::new(Cell::new(0));
+
+            let reader = BufReader::with_capacity(1024, Counting(code.as_bytes(), read.clone()));
+            let mut stream = StreamLexer::new("test.txt", reader).with_chunk_size(1024);
+
+            assert_eq!(stream.next_token().unwrap().kind, first);
+            assert!(read.get() <= 2 * 1024, "read {} of {} bytes for the first token", read.get(), code.len());
+
+            let count = stream.by_ref().map(Result::unwrap).count();
+            assert_eq!(count + 1, Lexer::new("test.txt", &code).count());
+            assert_eq!(read.get(), code.len());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_stream_lexer_invalid_utf8() {
+        let mut stream = StreamLexer::new("test.txt", &b"let a = \"\xFF\";"[..]);
+
+        assert!(matches!(stream.next_token(), Err(error) if error.kind() == std::io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn test_lexer_line_breaks() {
+        let code = "let a\r\nlet b\rlet c // note\rlet d\n/// doc\r\n'x\r";
+
+        let lines = Lexer::new("test.txt", code).map(|token| token.span().start().line()).collect::<Vec<_>>();
+        assert_eq!(lines, vec![1, 1, 2, 2, 3, 3, 4, 4, 5, 6, 7]);
+
+        let mut lexer = Lexer::new("test.txt", code);
+        let tokens = lexer.by_ref().collect::<Vec<_>>();
+        assert_eq!(tokens[8], token(TokenType::DocComment, TokenValue::String(" doc".to_string()), 5));
+        assert!(matches!(lexer.errors(), [LexError::UnterminatedChar { .. }]));
+    }
+
+    #[test]
+    fn test_lexer_bom_and_shebang() {
+        let code = "\u{FEFF}#!/usr/bin/env ja\nlet x = 1; #!";
+
+        let mut lexer = Lexer::new("test.txt", code).with_options(LexerOptions { lossless: true, ..LexerOptions::default() });
+
+        let first = lexer.next_token();
+        assert_eq!(first, token(TokenType::Let, TokenValue::String("let".to_string()), 2));
+        assert_eq!((first.span().start().line(), first.span().start().column()), (2, 1));
+        assert_eq!(first.trivia().iter().map(|trivia| trivia.kind()).collect::<Vec<_>>(), vec![TriviaKind::ByteOrderMark, TriviaKind::Shebang, TriviaKind::Newline]);
+
+        // Only the start of the file can hold a shebang.
+        let tokens = lexer.by_ref().collect::<Vec<_>>();
+        assert_eq!(tokens[4], token(TokenType::Error, TokenValue::String("#".to_string()), 2));
+        assert_eq!(lexer.errors().len(), 1);
+
+        let mut sources = SourceMap::new();
+        let file = sources.add_file("test.txt", code);
+        assert_eq!(sources.file(file).line_text(1), Some("#!/usr/bin/env ja"));
+    }
+
+    #[test]
+    fn test_source_file_columns() {
+        let code = "\u{FEFF}let s = \"😀é\"; x\rlet y";
+
+        let mut sources = SourceMap::new();
+        let file = sources.add_file("test.txt", code);
+        let tokens = sources.lexer(file).collect::<Vec<_>>();
+
+        let x = tokens[5].span().start();
+        assert_eq!((x.line(), x.column()), (1, 15));
+        assert_eq!(sources.file(file).utf8_column(x), 19);
+        assert_eq!(sources.file(file).utf16_column(x), 16);
+
+        let y = tokens[7].span().start();
+        assert_eq!((y.line(), y.column()), (2, 5));
+        assert_eq!((sources.file(file).utf8_column(y), sources.file(file).utf16_column(y)), (5, 5));
+        assert_eq!(sources.file(file).line_text(2), Some("let y"));
+
+        let options = LexerOptions { lossless: true, ..LexerOptions::default() };
+        let bom = sources.lexer(file).with_options(options).next_token().trivia()[0];
+        assert_eq!(bom.kind(), TriviaKind::ByteOrderMark);
+        for position in [bom.span().start(), bom.span().end()] {
+            assert_eq!((sources.file(file).utf8_column(position), sources.file(file).utf16_column(position)), (1, 1));
+        }
+    }
+
+    #[test]
+    fn test_source_encodings() {
+        let code = "let é = \"😀\";\nx";
+
+        let mut utf16 = vec![0xFF, 0xFE];
+        utf16.extend(code.encode_utf16().flat_map(u16::to_le_bytes));
+
+        let mut sources = SourceMap::new();
+        let (file, errors) = sources.add_bytes("utf16.ja", &utf16, Some(Encoding::Latin1));
+        assert!(errors.is_empty());
+        assert_eq!(sources.file(file).encoding(), Encoding::Utf16Le);
+        assert_eq!(sources.file(file).text(), format!("\u{FEFF}{}", code));
+
+        let tokens = sources.lexer(file).collect::<Vec<_>>();
+        let offsets = tokens.iter().map(|token| sources.file(file).original_offset(token.span().start().offset())).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![2, 10, 14, 18, 26, 30, 32]);
+
+        let latin1 = b"let caf\xE9 = 1;";
+        let decoded = decode(latin1, Some(Encoding::Latin1));
+        assert_eq!(decoded.text(), "let café = 1;");
+        assert_eq!(decoded.offsets().original_offset("let café".len()), 8);
+
+        let big_endian = decode(&[0xFE, 0xFF, 0xD8, 0x3D, 0xDE, 0x00, 0x00, 0x41], None);
+        assert_eq!(big_endian.text(), "\u{FEFF}😀A");
+    }
+
+    #[test]
+    fn test_source_invalid_encoding() {
+        let mut sources = SourceMap::new();
+        let (file, errors) = sources.add_bytes("broken.ja", b"let a = 1;\nlet \xC3b\xFF = \"\xE2\x82\";", None);
+
+        assert_eq!(sources.file(file).text(), "let a = 1;\nlet \u{FFFD}b\u{FFFD} = \"\u{FFFD}\";");
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0].to_string(), "invalid UTF-8 sequence");
+        assert_eq!(sources.location(errors[1].span()).to_string(), "broken.ja:2:7");
+        assert_eq!(sources.file(file).original_offset(errors[2].span().start().offset()), 22);
+        assert_eq!(sources.file(file).original_offset(errors[2].span().end().offset()), 24);
+
+        let (_, errors) = sources.add_bytes("odd.ja", &[0xFF, 0xFE, 0x41, 0x00, 0x00, 0xDC, 0x42], None);
+        assert!(matches!(errors.as_slice(), [LexError::InvalidEncoding { encoding: Encoding::Utf16Le, .. }, LexError::InvalidEncoding { .. }]));
+    }
+
+    #[test]
+    fn test_lexer_trojan_source() {
+        // An RLO in a comment, an LRI in a string, a zero-width joiner in an identifier and a Cyrillic
+        // `а` for a Latin `a`.
+        let code = "let x = 1; // \u{202E} } \u{2066}\nlet s = \"a\u{2066}\";\nlet a\u{200D}b = 2;\nlet a = 3; let \u{430} = 4;";
+
+        let mut lexer = Lexer::new("test.txt", code);
+        lexer.by_ref().for_each(drop);
+
+        assert!(lexer.errors().is_empty());
+        let warnings = lexer.warnings();
+        assert_eq!(warnings.len(), 5);
+
+        assert!(matches!(warnings[0], LexError::BidiControl { character: '\u{202E}', .. }));
+        assert_eq!((warnings[0].span().start().line(), warnings[0].span().start().column()), (1, 15));
+        assert!(matches!(warnings[1], LexError::BidiControl { character: '\u{2066}', .. }));
+        assert!(matches!(warnings[2], LexError::BidiControl { character: '\u{2066}', .. }));
+        assert_eq!((warnings[2].span().start().line(), warnings[2].span().start().column()), (2, 11));
+        assert!(matches!(warnings[3], LexError::InvisibleCharacter { character: '\u{200D}', .. }));
+        assert_eq!((warnings[3].span().start().line(), warnings[3].span().start().column()), (3, 6));
+        assert_eq!(warnings[4].to_string(), "identifier `\u{430}` looks like `a`");
+
+        // ASCII look-alikes are left alone.
+        let mut lexer = Lexer::new("test.txt", "let m = 3; let rn = 4; let O1 = 5; let Ol = 6; let l = 7; let I = 8;");
+        lexer.by_ref().for_each(drop);
+        assert!(lexer.warnings().is_empty());
+    }
+
+    // Untrusted input can hold any number of these, so finding their positions must not walk the
+    // text again for each one.
+    #[test]
+    fn test_lexer_hidden_character_runs() {
+        let line = format!("{}\r\n", "\u{202E}".repeat(100));
+        let code = format!("/* {} */ a{}", line.repeat(1000), "\u{200D}".repeat(100_000));
+
+        let mut lexer = Lexer::new("test.txt", &code);
+        lexer.by_ref().for_each(drop);
+
+        let warnings = lexer.warnings();
+        assert_eq!(warnings.len(), 200_000);
+
+        assert!(matches!(warnings[0], LexError::BidiControl { character: '\u{202E}', .. }));
+        assert_eq!((warnings[0].span().start().line(), warnings[0].span().start().column()), (1, 4));
+        assert_eq!((warnings[100].span().start().line(), warnings[100].span().start().column()), (2, 1));
+        assert_eq!((warnings[99_999].span().start().line(), warnings[99_999].span().start().column()), (1000, 100));
+
+        assert!(matches!(warnings[199_999], LexError::InvisibleCharacter { character: '\u{200D}', .. }));
+        assert_eq!((warnings[199_999].span().start().line(), warnings[199_999].span().start().column()), (1001, 100_005));
+    }
+
+    #[test]
+    fn test_lexer_warnings_as_errors() {
+        let code = "let s = \"\u{202E}\"; let p\u{430}ypal = paypal;";
+
+        let options = LexerOptions { warnings_as_errors: true, ..LexerOptions::default() };
+        let (tokens, errors) = {
+            let mut lexer = Lexer::new("test.txt", code).with_options(options);
+            let tokens = lexer.by_ref().collect::<Vec<_>>();
+            assert!(lexer.warnings().is_empty());
+            (tokens, lexer.take_errors())
+        };
+
+        assert_eq!(tokens.len(), 11);
+        assert!(matches!(errors.as_slice(), [LexError::BidiControl { .. }, LexError::MixedScriptIdentifier { .. }, LexError::ConfusableIdentifier { .. }]));
+    }
+
+    #[test]
+    fn test_token_type_classification() {
+        assert!(TokenType::Loop.is_keyword() && TokenType::In.is_keyword() && !TokenType::BoolVal.is_keyword());
+        assert!(TokenType::StringStart.is_literal() && TokenType::NullVal.is_literal() && !TokenType::Identifier.is_literal());
+        assert!(TokenType::Question.is_operator() && !TokenType::Arrow.is_operator());
+        assert!(TokenType::BitwiseShiftLeftAssign.is_assignment() && !TokenType::Equal.is_assignment());
+
+        assert!(TokenType::Multiply.binary_precedence() > TokenType::Plus.binary_precedence());
+        assert!(TokenType::Plus.binary_precedence() > TokenType::LessThan.binary_precedence());
+        assert!(TokenType::LogicalAnd.binary_precedence() > TokenType::LogicalOr.binary_precedence());
+        assert_eq!(TokenType::LogicalNot.binary_precedence(), None);
+
+        assert_eq!(TokenType::Minus.associativity(), Some(Associativity::Left));
+        assert_eq!(TokenType::Power.associativity(), Some(Associativity::Right));
+        assert_eq!(TokenType::Assign.associativity(), Some(Associativity::Right));
+        assert_eq!(TokenType::Equal.associativity(), Some(Associativity::NonAssociative));
+
+        assert!(TokenType::Minus.is_unary_prefix() && TokenType::BitwiseNot.is_unary_prefix() && !TokenType::Plus.is_unary_prefix());
+
+        assert_eq!(TokenType::BitwiseShiftRightAssign.to_string(), "`>>=`");
+        assert_eq!(TokenType::FatArrow.to_string(), "`=>`");
+        assert_eq!(TokenType::SelfValue.to_string(), "`self`");
+        assert_eq!(TokenType::Identifier.to_string(), "identifier");
+        assert_eq!(TokenType::Eof.to_string(), "end of file");
+
+        let kinds = [TokenType::Plus, TokenType::Plus, TokenType::Minus].into_iter().collect::<std::collections::HashSet<_>>();
+        assert_eq!(kinds.len(), 2);
+    }
+
+    #[test]
+    fn test_token_type_text_round_trips() {
+        let kinds = [TokenType::Power, TokenType::RangeInclusive, TokenType::BitwiseXorAssign, TokenType::Ellipsis, TokenType::DoubleColon, TokenType::Namespace, TokenType::Return];
+
+        for kind in kinds {
+            let text = kind.text().unwrap();
+            assert_eq!(Lexer::new("test.txt", text).next_token().kind, kind, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::fmt;
+
+use crate::encoding::{self, Encoding, OffsetMap};
+use crate::error::LexError;
+use crate::lexer::Lexer;
+use crate::symbol::SymbolTable;
+
+/// Identifies a file in the `SourceMap` it was added to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileId(pub(crate) u32);
+
+/// A point in a file: a byte offset and the 1-based line and character column it falls on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position {
+    pub(crate) offset: usize,
+    pub(crate) line: u32,
+    pub(crate) column: u32
+}
+
+impl Position {
+    pub fn new(offset: usize, line: u32, column: u32) -> Self {
+        Self {
+            offset, line, column
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+}
+
+/// The part of a file a token or error covers, from `start` up to but not including `end`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub(crate) file: FileId,
+    pub(crate) start: Position,
+    pub(crate) end: Position
+}
+
+impl Span {
+    pub fn new(file: FileId, start: Position, end: Position) -> Self {
+        Self {
+            file, start, end
+        }
+    }
+
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
+    pub fn start(&self) -> Position {
+        self.start
+    }
+
+    pub fn end(&self) -> Position {
+        self.end
+    }
+
+    /// The length in bytes.
+    pub fn len(&self) -> usize {
+        self.end.offset - self.start.offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start.offset == self.end.offset
+    }
+}
+
+/// A file's text with its line starts, for turning offsets into positions.
+pub struct SourceFile {
+    id: FileId,
+    path: String,
+    text: String,
+    line_starts: Vec<usize>,
+    encoding: Encoding,
+    // Back to the bytes the text was decoded from, for files loaded with `SourceMap::add_bytes`.
+    offsets: Option<OffsetMap>
+}
+
+impl SourceFile {
+    fn new(id: FileId, path: String, text: String) -> Self {
+        // Like the lexer, a byte order mark is not part of the first line, and `\r\n`, `\n` and a
+        // lone `\r` all end a line.
+        let mut line_starts = vec![if text.starts_with('\u{FEFF}') { '\u{FEFF}'.len_utf8() } else { 0 }];
+        let bytes = text.as_bytes();
+        line_starts.extend(bytes.iter().enumerate().filter(|&(index, &byte)| {
+            byte == b'\n' || (byte == b'\r' && bytes.get(index + 1) != Some(&b'\n'))
+        }).map(|(index, _)| index + 1));
+
+        Self {
+            id, path, text, line_starts, encoding: Encoding::Utf8, offsets: None
+        }
+    }
+
+    pub fn id(&self) -> FileId {
+        self.id
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The encoding the file was decoded from.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// The offset in the original bytes of the file that `offset` in its text was decoded from.
+    pub fn original_offset(&self, offset: usize) -> usize {
+        self.offsets.as_ref().map_or(offset, |offsets| offsets.original_offset(offset))
+    }
+
+    /// The position of a byte offset in the text, which must be a character boundary.
+    pub fn position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|&start| start <= offset).max(1);
+        let start = self.line_starts[line - 1].min(offset);
+        let column = self.text[start..offset].chars().count() + 1;
+
+        Position::new(offset, line as u32, column as u32)
+    }
+
+    /// An empty file, or one ending in a line break, still counts the last, empty line.
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Lines are 1-based, matching `Position::line`. The line terminator is not included.
+    pub fn line_text(&self, line: u32) -> Option<&str> {
+        let index = (line as usize).checked_sub(1)?;
+        let start = *self.line_starts.get(index)?;
+        let end = self.line_starts.get(index + 1).copied().unwrap_or(self.text.len());
+
+        Some(self.text[start..end].trim_end_matches(['\n', '\r']))
+    }
+
+    /// `Position::column` counts characters. These count the UTF-8 bytes or UTF-16 code units before
+    /// the position on its line instead, plus one; LSP clients usually want the UTF-16 column.
+    pub fn utf8_column(&self, position: Position) -> u32 {
+        (position.offset - self.line_start(position)) as u32 + 1
+    }
+
+    pub fn utf16_column(&self, position: Position) -> u32 {
+        self.text[self.line_start(position)..position.offset].encode_utf16().count() as u32 + 1
+    }
+
+    // Clamped like in `position`, since the first line starts after a byte order mark but the
+    // `ByteOrderMark` trivia starts at offset 0.
+    fn line_start(&self, position: Position) -> usize {
+        self.line_starts[position.line as usize - 1].min(position.offset)
+    }
+}
+
+/// Where a span starts, displayed as `path:line:column`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Location<'a> {
+    pub(crate) path: &'a str,
+    pub(crate) line: u32,
+    pub(crate) column: u32
+}
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.path, self.line, self.column)
+    }
+}
+
+/// The files of a compilation, sharing one symbol table.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+    symbols: SymbolTable
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_file(&mut self, path: impl Into<String>, text: impl Into<String>) -> FileId {
+        let id = FileId(self.files.len() as u32);
+        self.files.push(SourceFile::new(id, path.into(), text.into()));
+        id
+    }
+
+    /// Loads a file from raw bytes, decoded as described by `decode`. Invalid sequences
+    /// are replaced by U+FFFD and reported as errors.
+    pub fn add_bytes(&mut self, path: impl Into<String>, bytes: &[u8], declared: Option<Encoding>) -> (FileId, Vec<LexError>) {
+        let decoded = encoding::decode(bytes, declared);
+
+        let id = FileId(self.files.len() as u32);
+        let mut file = SourceFile::new(id, path.into(), decoded.text);
+        file.encoding = decoded.encoding;
+        file.offsets = Some(decoded.offsets);
+
+        let errors = decoded.invalid.iter().map(|range| LexError::InvalidEncoding {
+            encoding: decoded.encoding,
+            span: Span::new(id, file.position(range.start), file.position(range.end))
+        }).collect();
+
+        self.files.push(file);
+        (id, errors)
+    }
+
+    /// Panics if `id` came from a different map.
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0 as usize]
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &SourceFile> {
+        self.files.iter()
+    }
+
+    /// A lexer for the file that interns names into the map's symbol table.
+    pub fn lexer(&self, id: FileId) -> Lexer<'_> {
+        let file = self.file(id);
+        Lexer::with_file(id, &file.path, &file.text).with_symbols(self.symbols.clone())
+    }
+
+    /// The symbol table shared by the lexers of every file in the map.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub fn location(&self, span: Span) -> Location<'_> {
+        Location {
+            path: self.file(span.file).path(),
+            line: span.start.line,
+            column: span.start.column
+        }
+    }
+
+    /// The text covered by `span`.
+    pub fn source_text(&self, span: Span) -> &str {
+        &self.file(span.file).text[span.start.offset..span.end.offset]
+    }
+
+    /// Renders the first line covered by `span` with a caret underline, e.g.
+    ///
+    /// ```text
+    /// 3 | let x = $;
+    ///   |         ^
+    /// ```
+    pub fn snippet(&self, span: Span) -> String {
+        let file = self.file(span.file);
+        let line = span.start.line;
+        let text = file.line_text(line).unwrap_or("");
+
+        let gutter = line.to_string();
+        let padding = " ".repeat(gutter.len());
+        let indent = text.chars().take(span.start.column as usize - 1).map(|c| if c == '\t' { '\t' } else { ' ' }).collect::<String>();
+
+        let width = if span.end.line == line {
+            (span.end.column - span.start.column).max(1) as usize
+        } else {
+            (text.chars().count() + 1).saturating_sub(span.start.column as usize).max(1)
+        };
+
+        format!("{} | {}\n{} | {}{}", gutter, text, padding, indent, "^".repeat(width))
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+use crate::error::LexError;
+use crate::lexer::{Lexer, LexerOptions, LexerState, Token, TokenType};
+use crate::source::FileId;
+use crate::symbol::{Identifiers, SymbolTable};
+
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+// How far past the end of a token the lexer may look to decide where it ends, e.g. two characters
+// to tell `1..2` from `1.5` or four for `////`, in bytes.
+const LOOKAHEAD: usize = 16;
+
+/// Lexes a `BufRead` a chunk at a time, keeping only the text that has not been lexed yet in memory.
+/// Positions are relative to the start of the whole stream.
+///
+/// Tokens are only taken from the buffer if they end a few bytes before its end, so what follows
+/// could not have changed them. The last token, cut off by the end of the buffer, is
+/// lexed again once more of the stream has been read, so memory is bounded by the chunk size and
+/// the longest token rather than by the longest line or the input.
+pub struct StreamLexer<R> {
+    reader: R,
+    file: FileId,
+    path: String,
+    options: LexerOptions,
+    symbols: SymbolTable,
+    chunk_size: usize,
+    // Text read but not yet lexed, starting at `state`'s position.
+    buffer: String,
+    // The start of a UTF-8 sequence split across reads.
+    incomplete: Vec<u8>,
+    state: LexerState,
+    // The identifiers lexed so far, carried from one buffer's lexer to the next.
+    identifiers: Identifiers,
+    tokens: VecDeque<Token>,
+    errors: Vec<LexError>,
+    warnings: Vec<LexError>,
+    exhausted: bool,
+    // Set once the whole stream has been lexed.
+    eof: Option<Token>,
+    finished: bool
+}
+
+impl<R: BufRead> StreamLexer<R> {
+    /// Nothing is read until the first token is asked for.
+    pub fn new(path: impl Into<String>, reader: R) -> Self {
+        Self {
+            reader, file: FileId(0), path: path.into(), options: LexerOptions::default(), symbols: SymbolTable::new(), chunk_size: DEFAULT_CHUNK_SIZE, buffer: String::new(), incomplete: Vec::new(), state: LexerState::new(), identifiers: Identifiers::default(), tokens: VecDeque::new(), errors: Vec::new(), warnings: Vec::new(), exhausted: false, eof: None, finished: false
+        }
+    }
+
+    pub fn with_options(mut self, options: LexerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_symbols(mut self, symbols: SymbolTable) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// The least number of bytes to read before lexing again.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    pub fn take_errors(&mut self) -> Vec<LexError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    pub fn warnings(&self) -> &[LexError] {
+        &self.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<LexError> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    // Reads at least `size` bytes, or up to the end of the stream.
+    fn fill(&mut self, size: usize) -> io::Result<()> {
+        let mut read = 0;
+
+        while read < size {
+            let available = self.reader.fill_buf()?;
+            if available.is_empty() {
+                self.exhausted = true;
+                break;
+            }
+
+            self.incomplete.extend_from_slice(available);
+            let length = available.len();
+            self.reader.consume(length);
+            read += length;
+
+            self.decode()?;
+        }
+
+        if self.exhausted && !self.incomplete.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "stream ends in the middle of a UTF-8 sequence"));
+        }
+
+        Ok(())
+    }
+
+    // Moves the complete UTF-8 sequences read so far into the buffer.
+    fn decode(&mut self) -> io::Result<()> {
+        let valid = match std::str::from_utf8(&self.incomplete) {
+            Ok(text) => text.len(),
+            Err(error) if error.error_len().is_none() => error.valid_up_to(),
+            Err(error) => return Err(io::Error::new(io::ErrorKind::InvalidData, error))
+        };
+
+        // Only the prefix checked above is converted.
+        self.buffer.push_str(std::str::from_utf8(&self.incomplete[..valid]).unwrap());
+        self.incomplete.drain(..valid);
+        Ok(())
+    }
+
+    // Lexes the tokens that end far enough before the end of the buffer, or all of them once the
+    // stream is exhausted.
+    fn lex_buffer(&mut self) {
+        let base = self.state.position().offset;
+
+        let limit = match (base + self.buffer.len()).checked_sub(LOOKAHEAD) {
+            _ if self.exhausted => usize::MAX,
+            Some(limit) if limit >= base => limit,
+            _ => return
+        };
+
+        let mut lexer = Lexer::with_file(self.file, &self.path, &self.buffer).with_options(self.options).with_symbols(self.symbols.clone()).with_identifiers(std::mem::take(&mut self.identifiers)).resuming(&self.state);
+
+        loop {
+            lexer.save_state(&mut self.state);
+
+            if self.state.position().offset > limit {
+                break;
+            }
+
+            let token = lexer.next_token();
+            let eof = matches!(token.kind, TokenType::Eof);
+
+            if !self.exhausted && (eof || token.span.end.offset > limit || lexer.reached_end()) {
+                lexer.restore_state(&self.state);
+                break;
+            }
+
+            self.tokens.push_back(token);
+
+            if eof {
+                self.eof = self.tokens.back().cloned();
+                break;
+            }
+        }
+
+        self.errors.append(&mut lexer.take_errors());
+        self.warnings.append(&mut lexer.take_warnings());
+        self.identifiers = lexer.take_identifiers();
+
+        let consumed = self.state.position().offset - base;
+        self.buffer.drain(..consumed);
+    }
+
+    /// The next token, reading more of the stream when the buffered text runs out. Past the end of
+    /// the stream, keeps returning `Eof`.
+    pub fn next_token(&mut self) -> io::Result<Token> {
+        loop {
+            if let Some(token) = self.tokens.pop_front() {
+                return Ok(token);
+            }
+
+            // Like `Lexer`, keep returning `Eof` once the input has run out.
+            if let Some(eof) = &self.eof {
+                return Ok(eof.clone());
+            }
+
+            // Read at least as much again as is buffered, so a long token is re-lexed a logarithmic
+            // rather than linear number of times.
+            let size = self.chunk_size.max(self.buffer.len());
+            self.fill(size)?;
+            self.lex_buffer();
+        }
+    }
+}
+
+/// Yields every token including the final `Eof`, then stops. A read error ends the stream.
+impl<R: BufRead> Iterator for StreamLexer<R> {
+    type Item = io::Result<Token>;
+
+    fn next(&mut self) -> Option<io::Result<Token>> {
+        if self.finished {
+            return None;
+        }
+
+        let token = self.next_token();
+        self.finished = token.as_ref().map_or(true, |token| matches!(token.kind, TokenType::Eof));
+        Some(token)
+    }
+}
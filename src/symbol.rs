@@ -0,0 +1,142 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use unicode_security::skeleton;
+
+// No hash map outside `std`, so fall back to a B-tree there.
+#[cfg(feature = "std")]
+use std::collections::HashMap as Map;
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as Map;
+
+// With `std` the table is shared behind a lock, so lexers, source maps and incremental lexers can
+// be sent to and shared between threads. Without it there is no lock to use, so it stays
+// single-threaded. Names are `Arc<str>` either way, so `resolve` has the same signature whatever
+// the features.
+#[cfg(feature = "std")]
+#[derive(Clone, Default)]
+struct Shared(Arc<std::sync::Mutex<Interner>>);
+
+#[cfg(feature = "std")]
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Interner> {
+        // The interner is consistent between statements, so a panic elsewhere cannot corrupt it.
+        self.0.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+#[derive(Clone, Default)]
+struct Shared(alloc::rc::Rc<core::cell::RefCell<Interner>>);
+
+#[cfg(not(feature = "std"))]
+impl Shared {
+    fn lock(&self) -> core::cell::RefMut<'_, Interner> {
+        self.0.borrow_mut()
+    }
+}
+
+/// An interned name. Compares equal to another symbol from the same `SymbolTable` exactly when the
+/// names are equal.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(pub(crate) u32);
+
+impl Symbol {
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+#[derive(Default)]
+struct Interner {
+    symbols: Map<Arc<str>, Symbol>,
+    names: Vec<Arc<str>>,
+    // The first symbol with each Unicode TR39 skeleton, and for every symbol the first one with its
+    // skeleton, which stands for all names that look alike.
+    skeletons: Map<String, Symbol>,
+    skeleton_keys: Vec<Symbol>
+}
+
+/// A handle to an interner shared by every lexer of a compilation, so the same name gets the same
+/// `Symbol` in every file. Cloning the handle shares the table rather than copying it.
+#[derive(Clone, Default)]
+pub struct SymbolTable {
+    interner: Shared
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The symbol for `name`, adding it to the table if it is new.
+    pub fn intern(&self, name: &str) -> Symbol {
+        let mut interner = self.interner.lock();
+
+        if let Some(symbol) = interner.symbols.get(name) {
+            return *symbol;
+        }
+
+        let symbol = Symbol(interner.names.len() as u32);
+        let key = *interner.skeletons.entry(skeleton(name).collect()).or_insert(symbol);
+        interner.skeleton_keys.push(key);
+
+        let name = Arc::<str>::from(name);
+        interner.names.push(name.clone());
+        interner.symbols.insert(name, symbol);
+        symbol
+    }
+
+    /// Looks up a name without interning it.
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.interner.lock().symbols.get(name).copied()
+    }
+
+    /// The name a symbol was interned from, or `None` for a symbol from a different table.
+    pub fn resolve(&self, symbol: Symbol) -> Option<Arc<str>> {
+        self.interner.lock().names.get(symbol.0 as usize).cloned()
+    }
+
+    // The same for every symbol whose name looks like this one's, e.g. `pаypal` spelled with a
+    // Cyrillic `а` and `paypal`. Whether such a name was actually used is up to `Identifiers`, since
+    // the table outlives the text it was filled from.
+    pub(crate) fn skeleton_key(&self, symbol: Symbol) -> Symbol {
+        self.interner.lock().skeleton_keys.get(symbol.0 as usize).copied().unwrap_or(symbol)
+    }
+
+    pub fn len(&self) -> usize {
+        self.interner.lock().names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// The identifiers of one text by skeleton, to tell which earlier identifier a new one could be
+// mistaken for. Kept per text rather than in the shared table, so that names from other files, or
+// names since edited away, are never reported.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Identifiers {
+    // The first identifier with each skeleton key, and its offset.
+    first: Map<Symbol, (Symbol, usize)>
+}
+
+impl Identifiers {
+    // Records an identifier at `offset`, returning the earlier, different identifier that looks
+    // like it, if any.
+    pub(crate) fn insert(&mut self, key: Symbol, symbol: Symbol, offset: usize) -> Option<Symbol> {
+        let (first, _) = *self.first.entry(key).or_insert((symbol, offset));
+        Some(first).filter(|&first| first != symbol)
+    }
+
+    // Forgets the identifiers from `offset` on, when lexing is rewound to there.
+    pub(crate) fn truncate(&mut self, offset: usize) {
+        self.first.retain(|_, (_, first)| *first < offset);
+    }
+
+    // Whether the same identifier comes first for every skeleton, wherever it is.
+    pub(crate) fn same_as(&self, other: &Identifiers) -> bool {
+        self.first.len() == other.first.len() && self.first.iter().all(|(key, (symbol, _))| other.first.get(key).is_some_and(|(other, _)| other == symbol))
+    }
+}
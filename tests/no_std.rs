@@ -0,0 +1,25 @@
+// Builds the library without the `std` feature, where it is `#![no_std]`, so anything that reaches
+// for `std` outside the streaming lexer fails here rather than in a firmware build.
+
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn test_builds_without_std() {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+
+    // A target directory of its own, so the build neither waits on nor invalidates the one running
+    // this test.
+    let target = Path::new(env!("CARGO_TARGET_TMPDIR")).join("no_std");
+
+    let output = Command::new(cargo)
+        .args(["build", "--lib", "--no-default-features", "--manifest-path"])
+        .arg(&manifest)
+        .arg("--target-dir")
+        .arg(&target)
+        .output()
+        .expect("failed to run cargo");
+
+    assert!(output.status.success(), "no_std build failed:\n{}", String::from_utf8_lossy(&output.stderr));
+}
@@ -0,0 +1,135 @@
+// Pins down the public API described in the crate documentation. Anything that stops compiling or
+// passing here is a breaking change.
+
+use ja::{borrowed, IncrementalLexer, LexError, Lexer, LexerOptions, SourceMap, Span, Token, TokenType, TokenValue, TriviaKind};
+
+#[test]
+fn test_token_accessors() {
+    let mut lexer = Lexer::new("test.ja", "let x = 42u8;");
+
+    let token = lexer.next_token();
+    assert_eq!(token.kind(), TokenType::Let);
+    assert!(matches!(token.value(), TokenValue::String(text) if text == "let"));
+    assert_eq!(token.line(), 1);
+    assert_eq!(token.span().start().column(), 1);
+    assert_eq!(token.span().len(), 3);
+
+    let token = lexer.next_token();
+    assert_eq!(token.kind(), TokenType::Identifier);
+    assert!(token.symbol().is_some());
+    assert!(token.trivia().is_empty());
+
+    let token = lexer.nth(1).unwrap();
+    assert!(matches!(token.value(), TokenValue::UInt(42)));
+    assert!(token.suffix().is_some_and(|suffix| suffix.is_unsigned()));
+
+    let token: Token = Token::new(TokenType::Eof, TokenValue::Null, token.span());
+    assert_eq!(token.kind(), TokenType::Eof);
+}
+
+#[test]
+fn test_borrowed_token_accessors() {
+    let mut lexer = Lexer::new("test.ja", "\"a\" b");
+    let tokens = lexer.borrowed_tokens().collect::<Vec<_>>();
+
+    assert_eq!(tokens[0].kind(), TokenType::StringVal);
+    assert!(matches!(tokens[0].value(), borrowed::TokenValue::Str(text) if text == "a"));
+    assert_eq!(tokens[0].text(), "\"a\"");
+    assert_eq!(tokens[1].clone().into_owned().kind(), TokenType::Identifier);
+}
+
+// Every public enum may gain variants, so matches outside the crate need a wildcard arm.
+#[test]
+fn test_enums_are_non_exhaustive() {
+    let describe = |kind: TokenType| match kind {
+        TokenType::Identifier => "identifier",
+        TokenType::Eof => "end of input",
+        _ => "other"
+    };
+    assert_eq!(describe(TokenType::Eof), "end of input");
+
+    let trivia = |kind: TriviaKind| match kind {
+        TriviaKind::LineComment | TriviaKind::BlockComment => "comment",
+        TriviaKind::Whitespace | TriviaKind::Newline => "space",
+        _ => "other"
+    };
+    assert_eq!(trivia(TriviaKind::LineComment), "comment");
+
+    let (_, errors) = ja::tokenize("\"open");
+    let span: Span = match &errors[0] {
+        LexError::UnterminatedString { span } => *span,
+        error => error.span()
+    };
+    assert_eq!(span.start().offset(), 0);
+}
+
+#[test]
+fn test_options_from_default() {
+    let mut options = LexerOptions::default();
+    options.keep_comments = true;
+    options.lossless = false;
+    options.warnings_as_errors = false;
+
+    let token = Lexer::new("test.ja", "// note\nx").with_options(options).next_token();
+    assert_eq!(token.trivia()[0].kind(), TriviaKind::LineComment);
+}
+
+#[test]
+fn test_entry_points() {
+    let mut sources = SourceMap::new();
+    let file = sources.add_file("test.ja", "x + 1");
+    let tokens = sources.lexer(file).collect::<Vec<Token>>();
+    assert_eq!(tokens.last().map(Token::kind), Some(TokenType::Eof));
+    assert_eq!(sources.location(tokens[0].span()).to_string(), "test.ja:1:1");
+
+    let (file, errors) = sources.add_bytes("bytes.ja", b"y", None);
+    assert!(errors.is_empty());
+    assert_eq!(sources.file(file).encoding(), ja::Encoding::Utf8);
+
+    let mut incremental = IncrementalLexer::new("edit.ja", "x + 1");
+    incremental.edit(4..5, "2");
+    assert!(matches!(incremental.tokens()[2].value(), TokenValue::Int(2)));
+
+    // The same type with and without `std`, so enabling it elsewhere in a build breaks nothing.
+    let symbol = sources.lexer(file).next_token().symbol().unwrap();
+    let name: std::sync::Arc<str> = sources.symbols().resolve(symbol).unwrap();
+    assert_eq!(&*name, "y");
+
+    assert!(ja::is_reserved("while"));
+    assert_eq!(TokenType::Plus.to_string(), "`+`");
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_stream_entry_point() {
+    let kinds = ja::StreamLexer::new("stream.ja", std::io::Cursor::new("x + 1")).map(|token| token.map(|token| token.kind())).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(kinds, [TokenType::Identifier, TokenType::Plus, TokenType::IntVal, TokenType::Eof]);
+}
+
+// Lexing files in parallel and moving an incremental lexer to a worker thread both rely on these.
+#[test]
+#[cfg(feature = "std")]
+fn test_thread_safety() {
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    assert_send::<Lexer<'static>>();
+    assert_send::<IncrementalLexer>();
+    assert_send::<ja::StreamLexer<std::io::Cursor<Vec<u8>>>>();
+    assert_send::<SourceMap>();
+    assert_sync::<SourceMap>();
+    assert_send::<ja::SymbolTable>();
+    assert_sync::<ja::SymbolTable>();
+    assert_send::<Token>();
+
+    let mut sources = SourceMap::new();
+    let first = sources.add_file("a.ja", "shared");
+    let second = sources.add_file("b.ja", "shared");
+
+    let sources = &sources;
+    let symbols = std::thread::scope(|scope| {
+        let handles = [first, second].map(|file| scope.spawn(move || sources.lexer(file).next_token().symbol()));
+        handles.map(|handle| handle.join().unwrap())
+    });
+    assert_eq!(symbols[0], symbols[1]);
+}
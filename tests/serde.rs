@@ -0,0 +1,64 @@
+#![cfg(feature = "serde")]
+
+// Other tools read the JSON form of tokens and diagnostics, so its shape is pinned down by the
+// snapshots in `tests/snapshots`. Changing one is a breaking change; after an intended one, run the
+// tests with `UPDATE_SNAPSHOTS=1` to rewrite them.
+
+use std::path::Path;
+use std::{env, fs};
+
+use ja::{LexError, Lexer, LexerOptions, SourceMap, Span, Token, TokenType};
+
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots").join(format!("{}.json", name));
+
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, format!("{}\n", actual)).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_else(|error| panic!("cannot read {}: {}", path.display(), error));
+    assert_eq!(actual, expected.trim_end(), "{} differs from the snapshot", name);
+}
+
+fn lex(source: &str) -> Vec<Token> {
+    let mut options = LexerOptions::default();
+    options.keep_comments = true;
+
+    Lexer::new("test.ja", source).with_options(options).collect()
+}
+
+#[test]
+fn test_token_snapshot() {
+    let tokens = lex("// answer\nlet x = 42u8 + 1.5;\n'c' \"hi\" true null");
+    assert_snapshot("tokens", &serde_json::to_string_pretty(&tokens).unwrap());
+}
+
+#[test]
+fn test_error_snapshot() {
+    let mut sources = SourceMap::new();
+    let (file, mut errors) = sources.add_bytes("test.ja", b"let s = \"a\xFF\";\nlet c = '';\nlet n = 300u8;\n\"open", None);
+
+    let mut lexer = sources.lexer(file);
+    lexer.by_ref().for_each(drop);
+    errors.append(&mut lexer.take_errors());
+
+    assert_snapshot("errors", &serde_json::to_string_pretty(&errors).unwrap());
+}
+
+#[test]
+fn test_round_trip() {
+    let tokens = lex("fun f(a) { return \"${a}\\n\"; } // done");
+    let json = serde_json::to_string(&tokens).unwrap();
+
+    let decoded: Vec<Token> = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&decoded).unwrap(), json);
+    assert_eq!(decoded.last().map(Token::kind), Some(TokenType::Eof));
+
+    let (_, errors) = ja::tokenize("'ab'");
+    let decoded: Vec<LexError> = serde_json::from_str(&serde_json::to_string(&errors).unwrap()).unwrap();
+    assert_eq!(decoded, errors);
+
+    let span: Span = serde_json::from_str(r#"{"file":0,"start":{"offset":0,"line":1,"column":1},"end":{"offset":3,"line":1,"column":4}}"#).unwrap();
+    assert_eq!(span.len(), 3);
+}